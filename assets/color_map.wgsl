@@ -0,0 +1,68 @@
+struct SizeSettings {
+    width: u32,
+    height: u32,
+};
+
+struct ColorMapSettings {
+    gradient: array<vec4<f32>, 256>,
+    use_gradient: u32,
+    intensity_min: f32,
+    intensity_max: f32,
+    gamma: f32,
+};
+
+struct SpeciesColors {
+    colors: array<vec4<f32>>,
+};
+
+@group(0) @binding(0) var<uniform> size_settings: SizeSettings;
+@group(0) @binding(1) var<uniform> color_map_settings: ColorMapSettings;
+@group(0) @binding(2) var texture_view_read: texture_storage_2d_array<r32float, read>;
+@group(0) @binding(3) var texture_view_write: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(4) var<storage, read> species_colors: SpeciesColors;
+
+fn sample_gradient(intensity: f32) -> vec3<f32> {
+    let range = max(color_map_settings.intensity_max - color_map_settings.intensity_min, 0.000001);
+    let mapped = clamp((intensity - color_map_settings.intensity_min) / range, 0.0, 1.0);
+    let position = pow(mapped, color_map_settings.gamma) * 255.0;
+    let index = u32(floor(position));
+    let next_index = min(index + 1u, 255u);
+
+    return mix(
+        color_map_settings.gradient[index].rgb,
+        color_map_settings.gradient[next_index].rgb,
+        position - floor(position)
+    );
+}
+
+@compute @workgroup_size(8, 8)
+fn color_map_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x < 0u || 
+        invocation_id.x >= size_settings.width || 
+        invocation_id.y < 0u || 
+        invocation_id.y >= size_settings.height) {
+        return;
+    }
+
+    let coords = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+
+    // each layer holds the trail of a species, tinted with its display colour
+    var color = vec3<f32>(0.0, 0.0, 0.0);
+    var intensity = 0.0;
+    for (var layer: i32 = 0; layer < textureNumLayers(texture_view_read); layer = layer + 1) {
+        let trail = textureLoad(texture_view_read, coords, layer).r;
+        color = color + trail * species_colors.colors[layer].rgb;
+        intensity = intensity + trail;
+    }
+
+    // the summed trails pick the gradient colour, tinted by the mix of species laying them
+    if (color_map_settings.use_gradient != 0u) {
+        var tint = vec3<f32>(1.0, 1.0, 1.0);
+        if (intensity > 0.0) {
+            tint = color / intensity;
+        }
+        color = sample_gradient(intensity) * tint;
+    }
+
+    textureStore(texture_view_write, coords, vec4<f32>(color, 1.0));
+}
@@ -6,48 +6,112 @@ struct SizeSettings {
 struct DiffuseSettings {
     decay_rate: f32,
     diffuse_rate: f32,
+    boundary_mode: u32,
+    kernel_radius: u32,
+    kernel_matrix: u32,
+    kernel_weights: array<vec4<f32>, 32>,
 }
 
 struct TimeBuffer {
     time: f32,
     delta_time: f32,
+    frame: u32,
+    seed: u32,
 };
 
+let BOUNDARY_WRAP: u32 = 0u;
+
 @group(0) @binding(0) var<uniform> size_settings: SizeSettings;
 @group(0) @binding(1) var<uniform> diffuse_settings: DiffuseSettings;
 @group(0) @binding(2) var<uniform> time: TimeBuffer;
-@group(0) @binding(3) var trail_texture: texture_storage_2d<rgba16float, read>;
-@group(0) @binding(4) var diffuse_texture: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(3) var trail_texture: texture_storage_2d_array<r32float, read>;
+@group(0) @binding(4) var blur_texture_write: texture_storage_2d_array<r32float, write>;
+@group(0) @binding(5) var blur_texture_read: texture_storage_2d_array<r32float, read>;
+@group(0) @binding(6) var diffuse_texture: texture_storage_2d_array<r32float, write>;
+@group(0) @binding(7) var next_trail_texture: texture_storage_2d_array<r32float, write>;
+
+fn sample_coords(coords: vec2<i32>) -> vec2<i32> {
+    let size = vec2<i32>(i32(size_settings.width), i32(size_settings.height));
+
+    if (diffuse_settings.boundary_mode == BOUNDARY_WRAP) {
+        return ((coords % size) + size) % size;
+    }
 
+    return min(size - 1, max(vec2<i32>(0, 0), coords));
+}
+
+fn kernel_weight(index: i32) -> f32 {
+    return diffuse_settings.kernel_weights[index / 4][index % 4];
+}
+
+// first pass: the kernel row along x, or the whole custom matrix
 @compute @workgroup_size(8, 8)
-fn diffuse_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    if (invocation_id.x < 0u || 
-        invocation_id.x >= size_settings.width || 
-        invocation_id.y < 0u || 
+fn diffuse_blur_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x < 0u ||
+        invocation_id.x >= size_settings.width ||
+        invocation_id.y < 0u ||
         invocation_id.y >= size_settings.height) {
         return;
     }
-    
-    let coords = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
 
-    var sum: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
-    let original_col = textureLoad(trail_texture, coords);
-    for (var offset_x: i32 = -1; offset_x <= 1; offset_x = offset_x + 1) {
-        for (var offset_y: i32 = -1; offset_y <= 1; offset_y = offset_y + 1) {
-            let sample_x = min(size_settings.width - 1u, max(0u, invocation_id.x + u32(offset_x)));
-            let sample_y = min(size_settings.height - 1u, max(0u, invocation_id.y + u32(offset_y)));
+    let coords = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let radius = i32(diffuse_settings.kernel_radius);
+    let size = 2 * radius + 1;
 
-            let offset_coords = vec2<i32>(i32(sample_x), i32(sample_y));
-            let texture_state = textureLoad(trail_texture, offset_coords);
-            sum = sum + texture_state;
+    // every layer holds the trail of one species and is blurred on its own
+    for (var layer: i32 = 0; layer < textureNumLayers(trail_texture); layer = layer + 1) {
+        var sum: f32 = 0.0;
+        if (diffuse_settings.kernel_matrix != 0u) {
+            for (var offset_y: i32 = -radius; offset_y <= radius; offset_y = offset_y + 1) {
+                for (var offset_x: i32 = -radius; offset_x <= radius; offset_x = offset_x + 1) {
+                    let offset_coords = sample_coords(coords + vec2<i32>(offset_x, offset_y));
+                    let weight = kernel_weight((offset_y + radius) * size + offset_x + radius);
+                    sum = sum + weight * textureLoad(trail_texture, offset_coords, layer).r;
+                }
+            }
+        } else {
+            for (var offset_x: i32 = -radius; offset_x <= radius; offset_x = offset_x + 1) {
+                let offset_coords = sample_coords(coords + vec2<i32>(offset_x, 0));
+                sum = sum + kernel_weight(offset_x + radius) * textureLoad(trail_texture, offset_coords, layer).r;
+            }
         }
+
+        textureStore(blur_texture_write, coords, layer, vec4<f32>(sum));
+    }
+}
+
+// second pass: the same kernel row along y, then the blend with the trails and the decay
+@compute @workgroup_size(8, 8)
+fn diffuse_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x < 0u ||
+        invocation_id.x >= size_settings.width ||
+        invocation_id.y < 0u ||
+        invocation_id.y >= size_settings.height) {
+        return;
     }
 
-    let blurred_col = sum / 9.0;
+    let coords = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+    let radius = i32(diffuse_settings.kernel_radius);
+
     let diffuse_weight = clamp(diffuse_settings.diffuse_rate * time.delta_time, 0.0, 1.0);
-    let blurred_col = original_col * (1.0 - diffuse_weight) + blurred_col * diffuse_weight;
 
-    let output = max(vec4<f32>(0.0, 0.0, 0.0, 0.0), blurred_col - diffuse_settings.decay_rate * time.delta_time);
+    for (var layer: i32 = 0; layer < textureNumLayers(trail_texture); layer = layer + 1) {
+        var blurred_col = textureLoad(blur_texture_read, coords, layer).r;
+        if (diffuse_settings.kernel_matrix == 0u) {
+            blurred_col = 0.0;
+            for (var offset_y: i32 = -radius; offset_y <= radius; offset_y = offset_y + 1) {
+                let offset_coords = sample_coords(coords + vec2<i32>(0, offset_y));
+                blurred_col = blurred_col + kernel_weight(offset_y + radius) * textureLoad(blur_texture_read, offset_coords, layer).r;
+            }
+        }
+
+        let original_col = textureLoad(trail_texture, coords, layer).r;
+        let blended_col = original_col * (1.0 - diffuse_weight) + blurred_col * diffuse_weight;
 
-    textureStore(diffuse_texture, coords, output);
-}
\ No newline at end of file
+        let output = vec4<f32>(max(0.0, blended_col - diffuse_settings.decay_rate * time.delta_time));
+
+        // the agents of the next step deposit onto a copy of the trails they sense
+        textureStore(diffuse_texture, coords, layer, output);
+        textureStore(next_trail_texture, coords, layer, output);
+    }
+}
@@ -22,21 +22,38 @@ struct SpeciesSetting {
     @size(16) sensor_size: u32,
 };
 
+struct SpeciesSettings {
+    species: array<SpeciesSetting>,
+};
+
 struct SlimeSettings {
     num_agents: u32,
-    @size(12) trail_weight: f32,
-
-    species_settings: array<SpeciesSetting, 4>,// offset(16) align(16) size(32 * 4) stride(32)
-    // offset must be multiple of 16
-    // stride must be multiple of 16
-    // https://www.w3.org/TR/WGSL/#address-space-layout-constraints
+    trail_weight: f32,
+    boundary_mode: u32,
+    num_species: u32,
 };
 
+let BOUNDARY_WRAP: u32 = 0u;
+let BOUNDARY_REFLECT: u32 = 1u;
+let BOUNDARY_CLAMP: u32 = 2u;
+let BOUNDARY_RESPAWN: u32 = 3u;
+
 @group(0) @binding(0) var<uniform> size_settings: SizeSettings;
 @group(0) @binding(1) var<uniform> slime_settings: SlimeSettings;
 @group(0) @binding(2) var<storage, read_write> agents: Agents;
-@group(0) @binding(3) var texture_read: texture_storage_2d<rgba16float, read>;
+@group(0) @binding(3) var texture_read: texture_storage_2d_array<r32float, read>;
 @group(0) @binding(4) var texture_write: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(5) var<storage, read> species_settings: SpeciesSettings;
+
+fn sample_coords(coords: vec2<i32>) -> vec2<i32> {
+    let size = vec2<i32>(i32(size_settings.width), i32(size_settings.height));
+
+    if (slime_settings.boundary_mode == BOUNDARY_WRAP) {
+        return ((coords % size) + size) % size;
+    }
+
+    return min(size - 1, max(vec2<i32>(0, 0), coords));
+}
 
 fn draw_sense(agent: Agent, species_setting: SpeciesSetting, sensor_angle_offset: f32, color: vec4<f32>) {
     let sensor_angle = agent.angle + sensor_angle_offset;
@@ -52,10 +69,9 @@ fn draw_sense(agent: Agent, species_setting: SpeciesSetting, sensor_angle_offset
 
     for (offset_x = i32(species_setting.sensor_size) * -1; offset_x <= i32(species_setting.sensor_size); offset_x = offset_x + 1) {
         for (offset_y = i32(species_setting.sensor_size) * -1; offset_y <= i32(species_setting.sensor_size); offset_y = offset_y + 1) {
-            let sample_x = min(i32(size_settings.width) - 1, max(0, sensor_pos_x + offset_x));
-            let sample_y = min(i32(size_settings.height) - 1, max(0, sensor_pos_y + offset_y));
+            let sample_pos = sample_coords(vec2<i32>(sensor_pos_x + offset_x, sensor_pos_y + offset_y));
 
-            textureStore(texture_write, vec2<i32>(sample_x, sample_y), color);
+            textureStore(texture_write, sample_pos, color);
         }
     }
 }
@@ -63,7 +79,8 @@ fn draw_sense(agent: Agent, species_setting: SpeciesSetting, sensor_angle_offset
 @compute @workgroup_size(64, 1, 1)
 fn draw_sensor_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
 
-    let total_agents = arrayLength(&agents.agents);
+    // the agents buffer grows by chunks, only the live agents at its start are simulated
+    let total_agents = min(slime_settings.num_agents, arrayLength(&agents.agents));
     let agent_index = invocation_id.x;
 
     if (agent_index >= total_agents) {
@@ -71,7 +88,7 @@ fn draw_sensor_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     }
 
     var agent: Agent = agents.agents[agent_index];
-    var species_setting: SpeciesSetting = slime_settings.species_settings[agent.species_index];
+    var species_setting: SpeciesSetting = species_settings.species[agent.species_index];
 
     let sensor_angle = species_setting.sensor_angle_spacing * (3.1415 / 180.0);
     draw_sense(agent, species_setting, sensor_angle, vec4<f32>(1.0, 0.0, 0.0, 1.0));
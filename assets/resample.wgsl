@@ -0,0 +1,81 @@
+struct SizeSettings {
+    width: u32,
+    height: u32,
+};
+
+struct Agent {
+    position: vec2<f32>,
+    angle: f32,
+    species_index: u32,
+};
+
+struct Agents {
+    agents: array<Agent>,
+};
+
+struct AgentCount {
+    num_agents: u32,
+};
+
+@group(0) @binding(0) var<uniform> old_size: SizeSettings;
+@group(0) @binding(1) var<uniform> new_size: SizeSettings;
+@group(0) @binding(2) var<storage, read_write> agents: Agents;
+@group(0) @binding(3) var texture_read: texture_storage_2d_array<r32float, read>;
+@group(0) @binding(4) var texture_write: texture_storage_2d_array<r32float, write>;
+@group(0) @binding(5) var<uniform> agent_count: AgentCount;
+@group(0) @binding(6) var trail_write: texture_storage_2d_array<r32float, write>;
+
+fn load_clamped(coords: vec2<i32>, layer: i32) -> f32 {
+    let size = vec2<i32>(i32(old_size.width), i32(old_size.height));
+    return textureLoad(texture_read, min(size - 1, max(vec2<i32>(0, 0), coords)), layer).r;
+}
+
+fn resize_scale() -> vec2<f32> {
+    return vec2<f32>(f32(new_size.width), f32(new_size.height))
+        / vec2<f32>(f32(old_size.width), f32(old_size.height));
+}
+
+@compute @workgroup_size(8, 8)
+fn resample_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x >= new_size.width || invocation_id.y >= new_size.height) {
+        return;
+    }
+
+    // bilinear filtering of the old texture at the new pixel center
+    let position = (vec2<f32>(f32(invocation_id.x), f32(invocation_id.y)) + 0.5) / resize_scale() - 0.5;
+    let base = floor(position);
+    let t = position - base;
+    let coords = vec2<i32>(i32(base.x), i32(base.y));
+
+    let coords_write = vec2<i32>(i32(invocation_id.x), i32(invocation_id.y));
+
+    for (var layer: i32 = 0; layer < textureNumLayers(texture_read); layer = layer + 1) {
+        let top = mix(
+            load_clamped(coords, layer),
+            load_clamped(coords + vec2<i32>(1, 0), layer),
+            t.x,
+        );
+        let bottom = mix(
+            load_clamped(coords + vec2<i32>(0, 1), layer),
+            load_clamped(coords + vec2<i32>(1, 1), layer),
+            t.x,
+        );
+
+        // the display and trail textures of a side hold the same trails
+        let value = vec4<f32>(mix(top, bottom, t.y));
+        textureStore(texture_write, coords_write, layer, value);
+        textureStore(trail_write, coords_write, layer, value);
+    }
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn scale_agents_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let agent_index = invocation_id.x;
+
+    // agents past the live count are respawned before they are used again
+    if (agent_index >= agent_count.num_agents) {
+        return;
+    }
+
+    agents.agents[agent_index].position = agents.agents[agent_index].position * resize_scale();
+}
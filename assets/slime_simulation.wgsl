@@ -22,27 +22,42 @@ struct SpeciesSetting {
     @size(16) sensor_size: u32,
 };
 
+struct SpeciesSettings {
+    species: array<SpeciesSetting>,
+};
+
+// row after row, how much each species follows (positive) or avoids (negative) the others
+struct Interactions {
+    weights: array<f32>,
+};
+
 struct SlimeSettings {
     num_agents: u32,
-    @size(12) trail_weight: f32,
-
-    species_settings: array<SpeciesSetting, 4>,// offset(16) align(16) size(32 * 4) stride(32)
-    // offset must be multiple of 16
-    // stride must be multiple of 16
-    // https://www.w3.org/TR/WGSL/#address-space-layout-constraints
+    trail_weight: f32,
+    boundary_mode: u32,
+    num_species: u32,
 };
 
 struct TimeBuffer {
     time: f32,
     delta_time: f32,
+    frame: u32,
+    seed: u32,
 };
 
+let BOUNDARY_WRAP: u32 = 0u;
+let BOUNDARY_REFLECT: u32 = 1u;
+let BOUNDARY_CLAMP: u32 = 2u;
+let BOUNDARY_RESPAWN: u32 = 3u;
+
 @group(0) @binding(0) var<uniform> size_settings: SizeSettings;
 @group(0) @binding(1) var<uniform> slime_settings: SlimeSettings;
 @group(0) @binding(2) var<uniform> time: TimeBuffer;
 @group(0) @binding(3) var<storage, read_write> agents: Agents;
-@group(0) @binding(4) var texture_read: texture_storage_2d<rgba16float, read>;
-@group(0) @binding(5) var texture_write: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(4) var texture_read: texture_storage_2d_array<r32float, read>;
+@group(0) @binding(5) var texture_write: texture_storage_2d_array<r32float, write>;
+@group(0) @binding(6) var<storage, read> species_settings: SpeciesSettings;
+@group(0) @binding(7) var<storage, read> interactions: Interactions;
 
 fn hash(value: u32) -> u32 {
     var state = value;
@@ -59,6 +74,16 @@ fn scale_to_range(state: u32) -> f32 {
     return f32(state) / 4294967295.0;
 }
 
+fn sample_coords(coords: vec2<i32>) -> vec2<i32> {
+    let size = vec2<i32>(i32(size_settings.width), i32(size_settings.height));
+
+    if (slime_settings.boundary_mode == BOUNDARY_WRAP) {
+        return ((coords % size) + size) % size;
+    }
+
+    return min(size - 1, max(vec2<i32>(0, 0), coords));
+}
+
 fn sense(agent: Agent, species_setting: SpeciesSetting, sensor_angle_offset: f32) -> f32 {
     let sensor_angle = agent.angle + sensor_angle_offset;
     let sensor_dir = vec2<f32>(cos(sensor_angle), sin(sensor_angle));
@@ -68,19 +93,22 @@ fn sense(agent: Agent, species_setting: SpeciesSetting, sensor_angle_offset: f32
     let sensor_pos_x = i32(sensor_pos.x);
     let sensor_pos_y = i32(sensor_pos.y);
 
+    // every species has its own trail layer
+    let num_species = slime_settings.num_species;
+    let weights_offset = agent.species_index * num_species;
+
     var sum: f32;
     var offset_x: i32;
     var offset_y: i32;
 
     for (offset_x = i32(species_setting.sensor_size) * -1; offset_x <= i32(species_setting.sensor_size); offset_x = offset_x + 1) {
         for (offset_y = i32(species_setting.sensor_size) * -1; offset_y <= i32(species_setting.sensor_size); offset_y = offset_y + 1) {
-            let sample_x = min(i32(size_settings.width) - 1, max(0, sensor_pos_x + offset_x));
-            let sample_y = min(i32(size_settings.height) - 1, max(0, sensor_pos_y + offset_y));
+            let sample_pos = sample_coords(vec2<i32>(sensor_pos_x + offset_x, sensor_pos_y + offset_y));
 
-            let current_map = textureLoad(texture_read, vec2<i32>(sample_x, sample_y));
-            // TODO use a species mask
-            let mask = vec4<f32>(1.0, 1.0, 1.0, 1.0) * 2.0 - 1.0;
-            sum = sum + dot(mask, current_map);
+            for (var species: u32 = 0u; species < num_species; species = species + 1u) {
+                let current_map = textureLoad(texture_read, sample_pos, i32(species));
+                sum = sum + interactions.weights[weights_offset + species] * current_map.r;
+            }
         }
     }
 
@@ -90,7 +118,8 @@ fn sense(agent: Agent, species_setting: SpeciesSetting, sensor_angle_offset: f32
 @compute @workgroup_size(64, 1, 1)
 fn slime_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
 
-    let total_agents = arrayLength(&agents.agents);
+    // the agents buffer grows by chunks, only the live agents at its start are simulated
+    let total_agents = min(slime_settings.num_agents, arrayLength(&agents.agents));
     let agent_index = invocation_id.x;
 
     if (agent_index >= total_agents) {
@@ -98,12 +127,12 @@ fn slime_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     }
 
     var agent: Agent = agents.agents[agent_index];
-    var species_setting: SpeciesSetting = slime_settings.species_settings[agent.species_index];
+    var species_setting: SpeciesSetting = species_settings.species[agent.species_index];
 
     let random = hash(
         u32(agent.position.y) * size_settings .width
             + u32(agent.position.x)
-            + hash(u32(f32(agent_index) + time.time * 100000.0))
+            + hash(agent_index + hash(time.frame + hash(time.seed)))
     );
 
     let sensor_angle = species_setting.sensor_angle_spacing * (3.1415 / 180.0);
@@ -127,19 +156,47 @@ fn slime_main(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
     let direction = vec2<f32>(cos(agent.angle), sin(agent.angle));
     var new_pos: vec2<f32> = agent.position + direction * species_setting.move_speed * time.delta_time;
 
-    if (new_pos.x < 0.0 || new_pos.x > f32(size_settings.width) || new_pos.y < 0.0 || new_pos.y > f32(size_settings.height)) {
+    let size = vec2<f32>(f32(size_settings.width), f32(size_settings.height));
+    var deposit = true;
+
+    if (new_pos.x < 0.0 || new_pos.x > size.x || new_pos.y < 0.0 || new_pos.y > size.y) {
         let new_rand = hash(random);
         let random_angle = scale_to_range(new_rand) * 3.1415 * 2.0;
 
-        new_pos.x = min(f32(size_settings.width) - 1.0, max(0.0, new_pos.x));
-        new_pos.y = min(f32(size_settings.height) - 1.0, max(0.0, new_pos.y));
-        agents.agents[agent_index].angle = random_angle;
-    } else {
-        let current_pos = vec2<i32>(i32(new_pos.x), i32(new_pos.y));
-        // TODO use old_map with species mask
-        // let current_map = textureLoad(texture, current_pos);
+        let boundary_mode = slime_settings.boundary_mode;
+        if (boundary_mode == BOUNDARY_WRAP) {
+            new_pos = new_pos - floor(new_pos / size) * size;
+        } else if (boundary_mode == BOUNDARY_REFLECT) {
+            // mirror the position and the heading on the crossed edge
+            var angle = agents.agents[agent_index].angle;
+            if (new_pos.x < 0.0 || new_pos.x > size.x) {
+                new_pos.x = select(2.0 * size.x - new_pos.x, -new_pos.x, new_pos.x < 0.0);
+                angle = 3.1415 - angle;
+            }
+            if (new_pos.y < 0.0 || new_pos.y > size.y) {
+                new_pos.y = select(2.0 * size.y - new_pos.y, -new_pos.y, new_pos.y < 0.0);
+                angle = -angle;
+            }
+            new_pos = min(size - 1.0, max(vec2<f32>(0.0, 0.0), new_pos));
+            agents.agents[agent_index].angle = angle;
+        } else if (boundary_mode == BOUNDARY_RESPAWN) {
+            let rand_x = hash(new_rand);
+            let rand_y = hash(rand_x);
+            new_pos = vec2<f32>(scale_to_range(rand_x), scale_to_range(rand_y)) * (size - 1.0);
+            agents.agents[agent_index].angle = random_angle;
+        } else {
+            // BOUNDARY_CLAMP, and any unknown mode
+            new_pos = min(size - 1.0, max(vec2<f32>(0.0, 0.0), new_pos));
+            agents.agents[agent_index].angle = random_angle;
+            deposit = false;
+        }
+    }
+
+    if (deposit) {
+        let current_pos = sample_coords(vec2<i32>(i32(new_pos.x), i32(new_pos.y)));
 
-        textureStore(texture_write, current_pos, vec4<f32>(1.0, 1.0, 1.0, 1.0) * slime_settings.trail_weight);
+        // only the species layer is deposited, the other trails are kept
+        textureStore(texture_write, current_pos, i32(agent.species_index), vec4<f32>(slime_settings.trail_weight));
     }
 
     agents.agents[agent_index].position = new_pos;
@@ -0,0 +1,189 @@
+use std::f32::consts::TAU;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::types::{Agent, AgentDistribution, AppSettings, SpeciesAssignment};
+
+/// Radius of the disk agents spawned at the cursor are scattered in
+const CURSOR_SPAWN_RADIUS: f32 = 20.0;
+
+pub fn spawn_agents(app_settings: &AppSettings) -> Vec<Agent> {
+    let mut rng = StdRng::seed_from_u64(app_settings.seed);
+
+    let mut agents = distribute_agents(app_settings, app_settings.num_agents, &mut rng);
+    let center = [
+        app_settings.width as f32 / 2.0,
+        app_settings.height as f32 / 2.0,
+    ];
+
+    assign_species(&mut agents, app_settings, center, &mut rng);
+
+    agents
+}
+
+/// Spawns `count` agents appended after `first_index` live ones, either with the current
+/// distribution or in a small disk around `cursor`
+pub fn spawn_additional_agents(
+    app_settings: &AppSettings,
+    first_index: u32,
+    count: u32,
+    cursor: Option<[f32; 2]>,
+) -> Vec<Agent> {
+    // a different stream for every batch, still derived from the seed
+    let mut rng = StdRng::seed_from_u64(
+        app_settings.seed ^ (first_index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+    );
+
+    let (mut agents, center) = match cursor {
+        Some(center) => {
+            let agents = (0..count)
+                .map(|_| {
+                    let angle: f32 = rng.gen::<f32>() * TAU;
+                    let distance = rng.gen::<f32>().sqrt() * CURSOR_SPAWN_RADIUS;
+                    Agent {
+                        position: [
+                            center[0] + angle.cos() * distance,
+                            center[1] + angle.sin() * distance,
+                        ],
+                        angle,
+                        species_index: 0,
+                    }
+                })
+                .collect::<Vec<_>>();
+            (agents, center)
+        }
+        None => (
+            distribute_agents(app_settings, count, &mut rng),
+            [
+                app_settings.width as f32 / 2.0,
+                app_settings.height as f32 / 2.0,
+            ],
+        ),
+    };
+
+    assign_species(&mut agents, app_settings, center, &mut rng);
+
+    agents
+}
+
+fn distribute_agents(app_settings: &AppSettings, num_agents: u32, rng: &mut StdRng) -> Vec<Agent> {
+    match app_settings.agent_distribution {
+        AgentDistribution::InnerCircle => (0..num_agents)
+            .into_iter()
+            .map(|i| {
+                let angle: f32 = 2.0 * 3.1415 / (num_agents as f32) * i as f32;
+                let x = app_settings.width as f32 / 2.0 + angle.cos() * 200.0;
+                let y = app_settings.height as f32 / 2.0 + angle.sin() * 200.0;
+                Agent {
+                    position: [x, y],
+                    angle: 3.1415 + angle,
+                    species_index: 0,
+                }
+            })
+            .collect::<Vec<_>>(),
+        AgentDistribution::OuterCircle => (0..num_agents)
+            .into_iter()
+            .map(|i| {
+                let angle: f32 = 2.0 * 3.1415 / (num_agents as f32) * i as f32;
+                let x = app_settings.width as f32 / 2.0 + angle.cos() * 200.0;
+                let y = app_settings.height as f32 / 2.0 + angle.sin() * 200.0;
+                Agent {
+                    position: [x, y],
+                    angle: angle,
+                    species_index: 0,
+                }
+            })
+            .collect::<Vec<_>>(),
+        AgentDistribution::InnerDisk => (0..num_agents)
+            .into_iter()
+            .map(|_| {
+                let angle: f32 = rng.gen::<f32>() * 2.0 * 3.1415;
+                let x = app_settings.width as f32 / 2.0 + angle.cos() * rng.gen::<f32>() * 200.0;
+                let y = app_settings.height as f32 / 2.0 + angle.sin() * rng.gen::<f32>() * 200.0;
+                Agent {
+                    position: [x, y],
+                    angle: angle + 3.1415,
+                    species_index: 0,
+                }
+            })
+            .collect::<Vec<_>>(),
+        AgentDistribution::Random => (0..num_agents)
+            .into_iter()
+            .map(|_| {
+                let angle: f32 = rng.gen::<f32>() * 2.0 * 3.1415;
+                let x = rng.gen::<f32>() * app_settings.width as f32;
+                let y = rng.gen::<f32>() * app_settings.height as f32;
+                Agent {
+                    position: [x, y],
+                    angle: angle,
+                    species_index: 0,
+                }
+            })
+            .collect::<Vec<_>>(),
+    }
+}
+
+fn assign_species(
+    agents: &mut [Agent],
+    app_settings: &AppSettings,
+    [center_x, center_y]: [f32; 2],
+    rng: &mut StdRng,
+) {
+    let shares = normalized_shares(&app_settings.species_population, app_settings.num_species());
+
+    let mut counts = vec![0u32; shares.len()];
+
+    for (i, agent) in agents.iter_mut().enumerate() {
+        let species_index = match app_settings.species_assignment {
+            SpeciesAssignment::Interleaved => {
+                // pick the species lagging the most behind its share so far
+                let lag = |s: usize| shares[s] * (i + 1) as f32 - counts[s] as f32;
+                (1..shares.len()).fold(0, |best, s| if lag(s) > lag(best) { s } else { best })
+            }
+            SpeciesAssignment::Sectored => {
+                let angle = (agent.position[1] - center_y).atan2(agent.position[0] - center_x);
+                species_from_fraction(&shares, (angle / TAU).rem_euclid(1.0))
+            }
+            SpeciesAssignment::Random => species_from_fraction(&shares, rng.gen::<f32>()),
+        };
+
+        counts[species_index] += 1;
+        agent.species_index = species_index as u32;
+    }
+}
+
+fn normalized_shares(population: &[f32], num_species: usize) -> Vec<f32> {
+    let mut shares: Vec<f32> = (0..num_species.max(1))
+        .map(|species_index| {
+            population
+                .get(species_index)
+                .copied()
+                .unwrap_or(0.0)
+                .max(0.0)
+        })
+        .collect();
+    let total: f32 = shares.iter().sum();
+
+    if total <= 0.0 {
+        shares.iter_mut().for_each(|share| *share = 0.0);
+        shares[0] = 1.0;
+        return shares;
+    }
+
+    shares.iter_mut().for_each(|share| *share /= total);
+    shares
+}
+
+fn species_from_fraction(shares: &[f32], fraction: f32) -> usize {
+    let mut cumulated = 0.0;
+
+    for (species_index, share) in shares.iter().enumerate() {
+        cumulated += share;
+        if *share > 0.0 && fraction < cumulated {
+            return species_index;
+        }
+    }
+
+    // rounding errors, fall back to the last populated species
+    shares.iter().rposition(|share| *share > 0.0).unwrap_or(0)
+}
@@ -0,0 +1,236 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use crate::{
+    presets::list_user_presets,
+    types::{AgentDistribution, AppPreset, AppSettings},
+};
+
+#[derive(Parser, Debug)]
+#[command(about = "Slime mold simulation")]
+pub struct Cli {
+    /// RON settings file the simulation starts from
+    #[arg(long, conflicts_with = "preset")]
+    pub settings: Option<PathBuf>,
+
+    /// Built-in preset, or name of a preset saved from the GUI, the simulation starts from
+    #[arg(long)]
+    pub preset: Option<String>,
+
+    /// Simulation size, also the initial window size
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_resolution)]
+    pub resolution: Option<(u32, u32)>,
+
+    /// Number of agents
+    #[arg(long)]
+    pub agents: Option<u32>,
+
+    /// Seed of the agent spawning and the shader random numbers
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Agent distribution at creation
+    #[arg(long, value_enum)]
+    pub distribution: Option<Distribution>,
+
+    /// Overrides a single setting with a RON value, like `species[1].turn_speed=3`, applied last
+    #[arg(long = "set", value_name = "PATH=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+
+    /// Start in borderless fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Start with the settings window open, it is hidden by default and toggled with E
+    #[arg(long)]
+    pub show_gui: bool,
+
+    /// Recompile the shaders of the assets directory when they change on disk, keeping the agents
+    /// and trails
+    #[arg(long)]
+    pub watch_shaders: bool,
+
+    /// Continue from a snapshot saved from the GUI, with its own settings
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "settings",
+            "preset",
+            "resolution",
+            "agents",
+            "seed",
+            "distribution",
+            "overrides",
+        ]
+    )]
+    pub snapshot: Option<PathBuf>,
+
+    /// Run the simulation on the CPU, without a window, and write PNG frames
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Simulation steps run in headless mode
+    #[arg(long, default_value_t = 600)]
+    pub steps: u32,
+
+    /// Write a frame every K steps in headless mode
+    #[arg(long, value_name = "K", default_value_t = 10)]
+    pub every: u32,
+
+    /// Directory the headless frames are written to
+    #[arg(long, default_value = "frames")]
+    pub out: PathBuf,
+}
+
+impl Cli {
+    /// Settings the simulation starts from: the settings file or preset, then the single options,
+    /// then the overrides in the order they were given
+    pub fn app_settings(&self) -> Result<AppSettings, String> {
+        let mut app_settings = match (&self.settings, &self.preset) {
+            (Some(path), _) => AppSettings::load(path)?,
+            (None, Some(name)) => {
+                let mut app_settings = AppSettings {
+                    app_preset: find_preset(name)?,
+                    ..Default::default()
+                };
+                app_settings.apply_preset()?;
+                app_settings
+            }
+            (None, None) => AppSettings::default(),
+        };
+
+        if let Some((width, height)) = self.resolution {
+            app_settings.width = width;
+            app_settings.height = height;
+        }
+        if let Some(num_agents) = self.agents {
+            app_settings.num_agents = num_agents;
+        }
+        if let Some(seed) = self.seed {
+            app_settings.seed = seed;
+        }
+        if let Some(distribution) = self.distribution {
+            app_settings.agent_distribution = distribution.into();
+        }
+
+        for (path, value) in &self.overrides {
+            app_settings
+                .set_field(path, value)
+                .map_err(|error| format!("--set {}={}: {}", path, value, error))?;
+        }
+
+        Ok(app_settings)
+    }
+}
+
+/// Built-in presets first, then the user presets, compared case insensitively
+fn find_preset(name: &str) -> Result<AppPreset, String> {
+    let presets: Vec<AppPreset> = [AppPreset::Default, AppPreset::SuperNova]
+        .into_iter()
+        .chain(list_user_presets().into_iter().map(AppPreset::File))
+        .collect();
+
+    if let Some(preset) = presets
+        .iter()
+        .find(|preset| preset.name().eq_ignore_ascii_case(name))
+    {
+        return Ok(preset.clone());
+    }
+
+    let names: Vec<String> = presets.iter().map(AppPreset::name).collect();
+    Err(format!(
+        "Unknown preset {}, available presets: {}",
+        name,
+        names.join(", ")
+    ))
+}
+
+/// `AgentDistribution` values accepted by `--distribution`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Distribution {
+    InnerCircle,
+    OuterCircle,
+    InnerDisk,
+    Random,
+}
+
+impl From<Distribution> for AgentDistribution {
+    fn from(distribution: Distribution) -> Self {
+        match distribution {
+            Distribution::InnerCircle => AgentDistribution::InnerCircle,
+            Distribution::OuterCircle => AgentDistribution::OuterCircle,
+            Distribution::InnerDisk => AgentDistribution::InnerDisk,
+            Distribution::Random => AgentDistribution::Random,
+        }
+    }
+}
+
+fn parse_resolution(resolution: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("expected WIDTHxHEIGHT, got {}", resolution);
+
+    let (width, height) = resolution.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+fn parse_override(setting: &str) -> Result<(String, String), String> {
+    setting
+        .split_once('=')
+        .map(|(path, value)| (path.trim().to_string(), value.trim().to_string()))
+        .ok_or_else(|| format!("expected PATH=VALUE, got {}", setting))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_resolution_reads_width_and_height() {
+        assert_eq!(parse_resolution("1920x1080"), Ok((1920, 1080)));
+        assert_eq!(parse_resolution(" 640 x 480 "), Ok((640, 480)));
+
+        for resolution in [
+            "1920",
+            "1920x",
+            "x1080",
+            "0x1080",
+            "1920x0",
+            "1920*1080",
+            "-1x5",
+            "axb",
+        ] {
+            assert!(parse_resolution(resolution).is_err(), "{}", resolution);
+        }
+    }
+
+    #[test]
+    fn distribution_maps_to_the_settings_value() {
+        let cli = Cli::try_parse_from(["slime", "--distribution", "inner-disk"]).unwrap();
+        let app_settings = cli.app_settings().unwrap();
+        assert_eq!(
+            app_settings.agent_distribution,
+            AgentDistribution::InnerDisk
+        );
+
+        assert!(Cli::try_parse_from(["slime", "--distribution", "square"]).is_err());
+    }
+
+    #[test]
+    fn snapshot_conflicts_with_the_settings_it_restores() {
+        assert!(Cli::try_parse_from(["slime", "--snapshot", "run.bin"]).is_ok());
+        for option in [
+            ["--seed", "5"],
+            ["--agents", "10"],
+            ["--preset", "SuperNova"],
+        ] {
+            let arguments = ["slime", "--snapshot", "run.bin", option[0], option[1]];
+            assert!(Cli::try_parse_from(arguments).is_err(), "{}", option[0]);
+        }
+    }
+}
@@ -6,7 +6,8 @@ use bevy::{
         main_graph::node::CAMERA_DRIVER,
         render_asset::RenderAssets,
         render_graph::RenderGraph,
-        renderer::RenderDevice,
+        render_resource::PipelineCache,
+        renderer::{RenderDevice, RenderQueue},
         RenderApp, RenderStage,
     },
 };
@@ -14,9 +15,14 @@ use bevy::{
 use crate::{
     compute_render_node::ComputeRenderNode,
     compute_slime_pipeline::{ComputeSlimeBindGroup, ComputeSlimePipeline},
-    pipeline::Pipeline,
+    pipeline::{
+        pass_graph::AddComputePass, ColorMapPipeline, DiffusePipeline, DrawSensorPipeline,
+        Pipeline, SlimeSimPipeline,
+    },
     types::AppSettings,
-    AppSettingsUpdated, AppShouldReset,
+    AgentSpawnCursor, AppPassTimings, AppPaused, AppPipelineError, AppProfiling, AppRecording,
+    AppRestoreSnapshot, AppSaveScreenshot, AppSaveSnapshot, AppSettingsUpdated, AppShouldReset,
+    AppStepOnce,
 };
 
 pub struct ComputePlugin;
@@ -24,32 +30,113 @@ pub struct ComputePlugin;
 impl Plugin for ComputePlugin {
     fn build(&self, app: &mut App) {
         let app_settings = app.world.get_resource::<AppSettings>().cloned().unwrap();
+        let app_restore_snapshot = app
+            .world
+            .get_resource::<AppRestoreSnapshot>()
+            .cloned()
+            .unwrap();
+        // shared with the render world, which reports its errors through it
+        let app_pipeline_error = app
+            .world
+            .get_resource::<AppPipelineError>()
+            .cloned()
+            .unwrap();
+        let app_pass_timings = app.world.get_resource::<AppPassTimings>().cloned().unwrap();
         app.add_plugin(ExtractResourcePlugin::<AppSettings>::default());
         app.add_plugin(ExtractResourcePlugin::<AppShouldReset>::default());
         app.add_plugin(ExtractResourcePlugin::<AppSettingsUpdated>::default());
         app.add_plugin(ExtractResourcePlugin::<ComputeSlimeDisplayImage>::default());
-        app.add_plugin(ExtractResourcePlugin::<ComputeSlimeTime>::default());
+        app.add_plugin(ExtractResourcePlugin::<AppPaused>::default());
+        app.add_plugin(ExtractResourcePlugin::<AppStepOnce>::default());
+        app.add_plugin(ExtractResourcePlugin::<AgentSpawnCursor>::default());
+        app.add_plugin(ExtractResourcePlugin::<AppSaveSnapshot>::default());
+        app.add_plugin(ExtractResourcePlugin::<AppSaveScreenshot>::default());
+        app.add_plugin(ExtractResourcePlugin::<AppRestoreSnapshot>::default());
+        app.add_plugin(ExtractResourcePlugin::<AppRecording>::default());
+        app.add_plugin(ExtractResourcePlugin::<AppProfiling>::default());
+
+        app.add_compute_pass(|world| Box::new(SlimeSimPipeline::new(world)))
+            .add_compute_pass(|world| Box::new(DiffusePipeline::new(world)))
+            .add_compute_pass(|world| Box::new(ColorMapPipeline::new(world)))
+            .add_compute_pass(|world| Box::new(DrawSensorPipeline::new(world)));
 
         let render_app = app.sub_app_mut(RenderApp);
         render_app
             .insert_resource(app_settings)
-            .init_resource::<ComputeSlimePipeline>()
-            .add_system_to_stage(RenderStage::Prepare, reload_pipeline)
+            .insert_resource(app_restore_snapshot)
+            .insert_resource(AppRecording(None))
+            .insert_resource(AppProfiling(false))
+            .insert_resource(app_pipeline_error)
+            .insert_resource(app_pass_timings)
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                build_pipeline.exclusive_system().at_start(),
+            )
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                resize_simulation.before(resize_agents),
+            )
+            .add_system_to_stage(RenderStage::Prepare, resize_agents)
             .add_system_to_stage(RenderStage::Queue, ComputePlugin::queue_bind_group);
 
+        let compute_render_node = ComputeRenderNode::new(&render_app.world);
         let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
-        render_graph.add_node("slime_simulation", ComputeRenderNode::default());
+        render_graph.add_node("slime_simulation", compute_render_node);
         render_graph
             .add_node_edge("slime_simulation", CAMERA_DRIVER)
             .unwrap();
     }
 }
 
-fn reload_pipeline(mut commands: Commands, app_should_reset: Res<AppShouldReset>) {
+/// Builds the pipeline in the first frame, once every plugin added its passes, and again on reset
+fn build_pipeline(world: &mut World) {
+    if world.resource::<AppShouldReset>().0 || !world.contains_resource::<ComputeSlimePipeline>() {
+        world.remove_resource::<ComputeSlimePipeline>();
+        let compute_slime_pipeline = ComputeSlimePipeline::new(world);
+        world.insert_resource(compute_slime_pipeline);
+    }
+}
+
+fn resize_simulation(
+    mut compute_slime_pipeline: ResMut<ComputeSlimePipeline>,
+    app_settings: Res<AppSettings>,
+    app_should_reset: Res<AppShouldReset>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    pipeline_cache: Res<PipelineCache>,
+) {
+    // the pipeline was rebuilt at the new size
+    if app_should_reset.0 {
+        return;
+    }
+
+    compute_slime_pipeline.resize(
+        &render_device,
+        &render_queue,
+        &pipeline_cache,
+        &app_settings,
+    );
+}
+
+fn resize_agents(
+    mut compute_slime_pipeline: ResMut<ComputeSlimePipeline>,
+    app_settings: Res<AppSettings>,
+    app_should_reset: Res<AppShouldReset>,
+    agent_spawn_cursor: Res<AgentSpawnCursor>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    // the pipeline was rebuilt with the new agent count
     if app_should_reset.0 {
-        commands.remove_resource::<ComputeSlimePipeline>();
-        commands.init_resource::<ComputeSlimePipeline>();
+        return;
     }
+
+    compute_slime_pipeline.resize_agents(
+        &render_device,
+        &render_queue,
+        &app_settings,
+        agent_spawn_cursor.0,
+    );
 }
 
 impl ComputePlugin {
@@ -72,14 +159,3 @@ impl ComputePlugin {
 
 #[derive(Clone, Deref, ExtractResource, Debug)]
 pub struct ComputeSlimeDisplayImage(pub Handle<Image>);
-
-#[derive(Deref)]
-pub struct ComputeSlimeTime(pub Time);
-
-impl ExtractResource for ComputeSlimeTime {
-    type Source = Time;
-
-    fn extract_resource(time: &Self::Source) -> Self {
-        ComputeSlimeTime(time.clone())
-    }
-}
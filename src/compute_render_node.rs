@@ -1,36 +1,102 @@
 use bevy::{
-    prelude::World,
+    log::{error, info},
+    prelude::{Image, World},
     render::{
+        render_asset::RenderAssets,
         render_graph::*,
         render_resource::PipelineCache,
-        renderer::{RenderContext, RenderQueue},
+        renderer::{RenderContext, RenderDevice, RenderQueue},
     },
 };
 
 use crate::{
-    compute_plugin::ComputeSlimeTime,
-    compute_slime_pipeline::{ComputeSlimePipeline, ComputeTimeUpdate},
-    pipeline::Pipeline,
-    types::{AppSettings, TimeBuffer},
-    AppSettingsUpdated,
+    compute_plugin::ComputeSlimeDisplayImage,
+    compute_slime_pipeline::{
+        read_texture, shader_seed, ComputeSlimeExecute, ComputeSlimePipeline, ComputeTimeUpdate,
+        MAX_SUBSTEPS,
+    },
+    cpu_simulation::{TrailMap, RGBA16F_TEXEL_SIZE},
+    pass_timer::PassTimer,
+    pipeline::{PingPongSide, Pipeline},
+    recording::{Recorder, RecordingSettings},
+    screenshot::save_image,
+    types::{AppSettings, SizeSettings, TimeBuffer},
+    AppPassTimings, AppPaused, AppPipelineError, AppProfiling, AppRecording, AppRestoreSnapshot,
+    AppSaveScreenshot, AppSaveSnapshot, AppSettingsUpdated, AppShouldReset, AppStepOnce,
 };
 
 pub struct ComputeRenderNode {
     state: ComputeSlimeState,
+    time: f32,
+    frame: u32,
+    steps: u32,
+    /// Part of a step the time scale left over, run by a later frame
+    step_fraction: f32,
+    /// Ping-pong side the steps of this frame start from
+    side: PingPongSide,
+    /// Recording requested by the main world, kept after a failure so it is not restarted
+    recording: Option<RecordingSettings>,
+    recorder: Option<Recorder>,
+    timer: PassTimer,
 }
 
-impl Default for ComputeRenderNode {
-    fn default() -> Self {
+impl ComputeRenderNode {
+    pub fn new(world: &World) -> Self {
+        let (time, frame) = restored_clock(world.resource::<AppRestoreSnapshot>());
+
         ComputeRenderNode {
             state: ComputeSlimeState::Init,
+            time,
+            frame,
+            steps: 0,
+            step_fraction: 0.0,
+            side: 0,
+            recording: None,
+            recorder: None,
+            timer: PassTimer::new(
+                world.resource::<RenderDevice>(),
+                world.resource::<RenderQueue>(),
+            ),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum ComputeSlimeState {
     Init,
     Loaded,
+    /// A shader changed on disk, nothing runs until its pipelines compile again. The agents and
+    /// the trails are kept
+    Reloading,
+    /// Nothing runs until the failed pipeline compiles
+    Error(PipelineError),
+}
+
+/// A pass that cannot run, its pipeline failed to compile or the pass graph could not be built
+#[derive(Clone, Debug, PartialEq)]
+pub struct PipelineError {
+    /// Pass the pipeline belongs to
+    pub pass: &'static str,
+    /// None when the pass failed before any of its pipelines compiled
+    pub entry_point: Option<String>,
+    pub message: String,
+}
+
+impl PipelineError {
+    pub fn summary(&self) -> String {
+        match &self.entry_point {
+            Some(entry_point) => {
+                format!("The {} pass ({}) failed to compile", self.pass, entry_point)
+            }
+            None => format!("The {} pass cannot run", self.pass),
+        }
+    }
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:\n{}", self.summary(), self.message)
+    }
 }
 
 impl Node for ComputeRenderNode {
@@ -38,22 +104,160 @@ impl Node for ComputeRenderNode {
         let compute_slime_pipeline = world.resource::<ComputeSlimePipeline>();
         let pipeline_cache = world.resource::<PipelineCache>();
         let render_queue = world.resource::<RenderQueue>();
-        let compute_slime_time = world.resource::<ComputeSlimeTime>();
 
         let app_settings = world.resource::<AppSettings>();
         let app_settings_updated = world.resource::<AppSettingsUpdated>();
+        let app_should_reset = world.resource::<AppShouldReset>();
+        let app_paused = world.resource::<AppPaused>();
+        let app_step_once = world.resource::<AppStepOnce>();
+        let app_save_snapshot = world.resource::<AppSaveSnapshot>();
+        let app_save_screenshot = world.resource::<AppSaveScreenshot>();
+        let app_restore_snapshot = world.resource::<AppRestoreSnapshot>();
+        let app_recording = world.resource::<AppRecording>();
 
-        let time_buffer = TimeBuffer {
-            time: compute_slime_time.0.seconds_since_startup() as f32,
-            delta_time: compute_slime_time.0.delta_seconds(),
-        };
+        // agents are respawned, restart the clock so that seeded runs replay
+        if app_should_reset.0 {
+            (self.time, self.frame) = restored_clock(app_restore_snapshot);
+            self.step_fraction = 0.0;
+        }
+
+        // the display texture holds the trails of the last steps, the clock has not moved since
+        if let Some(path) = &app_save_snapshot.0 {
+            let render_device = world.resource::<RenderDevice>();
+            let saved = compute_slime_pipeline
+                .read_snapshot(
+                    render_device,
+                    render_queue,
+                    app_settings,
+                    self.time,
+                    self.frame,
+                )
+                .and_then(|snapshot| snapshot.save(path));
+
+            match saved {
+                Ok(()) => info!("Snapshot saved to {}", path.display()),
+                Err(error) => error!("{}", error),
+            }
+        }
+
+        // the displayed image still holds the colour map of the last frame
+        if let Some(request) = &app_save_screenshot.0 {
+            let saved = read_display_image(world)
+                .and_then(|image| save_image(&request.path, request.format, &image, app_settings));
+
+            match saved {
+                Ok(()) => info!("Screenshot saved to {}", request.path.display()),
+                Err(error) => error!("{}", error),
+            }
+        }
+
+        if app_recording.0 != self.recording {
+            if let Some(recorder) = self.recorder.take() {
+                finish_recording(recorder);
+            }
+
+            self.recording = app_recording.0.clone();
+            if let Some(recording) = &self.recording {
+                info!("Recording to {}", recording.path.display());
+                self.recorder = Some(Recorder::new(recording.clone()));
+            }
+        }
+
+        // only frames that advanced the simulation are counted, every one of them by the same
+        // fixed steps, so the capture time does not show in the recording
+        if self.steps > 0 {
+            if let Some(recorder) = &mut self.recorder {
+                let recorded = if recorder.next_frame() {
+                    read_display_image(world)
+                        .and_then(|image| recorder.write_frame(&image, app_settings))
+                } else {
+                    Ok(())
+                };
+
+                if let Err(error) = recorded {
+                    error!("{}", error);
+                    if let Some(recorder) = self.recorder.take() {
+                        finish_recording(recorder);
+                    }
+                }
+            }
+        }
 
         if app_settings_updated.0 {
             compute_slime_pipeline.update_settings(render_queue, app_settings);
         }
 
-        compute_slime_pipeline.update_time(render_queue, &ComputeTimeUpdate { time_buffer });
+        let previous_state = self.state.clone();
         compute_slime_pipeline.update_state(pipeline_cache, &mut self.state);
+
+        match (&previous_state, &self.state) {
+            (ComputeSlimeState::Loaded, ComputeSlimeState::Reloading) => {
+                info!("Shaders changed, recompiling")
+            }
+            (ComputeSlimeState::Reloading, ComputeSlimeState::Loaded) => {
+                info!("Shaders recompiled")
+            }
+            _ => {}
+        }
+
+        // the main world shows the error until the shader is fixed
+        let error = pipeline_error(&self.state);
+        if error != pipeline_error(&previous_state) {
+            match &error {
+                Some(error) => error!("{}", error),
+                None => info!("Compute pipelines compiled"),
+            }
+
+            *world.resource::<AppPipelineError>().0.lock().unwrap() = error;
+        }
+
+        // the simulation only advances by fixed steps, whatever the frame rate, the time scale
+        // changes how many of them run
+        self.steps = match self.state {
+            ComputeSlimeState::Init
+            | ComputeSlimeState::Reloading
+            | ComputeSlimeState::Error(_) => 0,
+            ComputeSlimeState::Loaded if app_paused.0 => u32::from(app_step_once.0),
+            ComputeSlimeState::Loaded => self.scaled_steps(app_settings),
+        };
+
+        let delta_time = app_settings.time_step;
+        let time_buffers = (0..self.steps)
+            .map(|_| {
+                let time_buffer = TimeBuffer {
+                    time: self.time,
+                    delta_time,
+                    frame: self.frame,
+                    seed: shader_seed(app_settings.seed),
+                };
+
+                self.time += delta_time;
+                self.frame = self.frame.wrapping_add(1);
+
+                time_buffer
+            })
+            .collect();
+
+        compute_slime_pipeline.update_time(render_queue, &ComputeTimeUpdate { time_buffers });
+
+        // the GPU timings of earlier frames come back a few frames late
+        let profiling = world.resource::<AppProfiling>().0;
+        let frames = self.timer.collect();
+        if profiling || !frames.is_empty() {
+            let mut timings = world.resource::<AppPassTimings>().0.lock().unwrap();
+            timings.frames.extend(frames);
+            timings.gpu_timestamps = self.timer.gpu_timestamps();
+            if profiling {
+                timings.agent_updates +=
+                    compute_slime_pipeline.num_agents as u64 * self.steps as u64;
+            }
+        }
+        self.timer.begin_frame(profiling);
+
+        // every step swaps the sides, the next frame starts from the trails written last
+        let mut compute_slime_pipeline = world.resource_mut::<ComputeSlimePipeline>();
+        self.side = compute_slime_pipeline.side;
+        compute_slime_pipeline.side = (self.side + self.steps as usize) % 2;
     }
 
     fn run(
@@ -66,8 +270,88 @@ impl Node for ComputeRenderNode {
         let pipeline_cache = world.resource::<PipelineCache>();
         let app_settings = world.resource::<AppSettings>();
 
-        compute_slime_pipeline.execute(render_context, pipeline_cache, &self.state, app_settings);
+        let execute_settings = ComputeSlimeExecute {
+            steps: self.steps,
+            side: self.side,
+            render_sensors: app_settings.render_sensors,
+            timer: &self.timer,
+        };
+
+        compute_slime_pipeline.execute(
+            render_context,
+            pipeline_cache,
+            &self.state,
+            &execute_settings,
+        );
 
         Ok(())
     }
 }
+
+impl ComputeRenderNode {
+    /// Steps per frame times the time scale, the fraction left is carried over to the next frames
+    /// so that slow motion runs a step every few frames
+    fn scaled_steps(&mut self, app_settings: &AppSettings) -> u32 {
+        let substeps = app_settings.substeps.clamp(1, MAX_SUBSTEPS) as f32;
+        let scaled = substeps * app_settings.time_scale.max(0.0) + self.step_fraction;
+        let steps = (scaled.floor() as u32).min(MAX_SUBSTEPS);
+
+        // steps past the cap are dropped rather than piling up
+        self.step_fraction = if steps == MAX_SUBSTEPS {
+            0.0
+        } else {
+            scaled - steps as f32
+        };
+
+        steps
+    }
+}
+
+fn pipeline_error(state: &ComputeSlimeState) -> Option<PipelineError> {
+    match state {
+        ComputeSlimeState::Error(error) => Some(error.clone()),
+        _ => None,
+    }
+}
+
+/// Reads back the image the colour map writes and the window shows
+fn read_display_image(world: &World) -> Result<TrailMap, String> {
+    let gpu_images = world.resource::<RenderAssets<Image>>();
+    let display_image = world.resource::<ComputeSlimeDisplayImage>();
+
+    let gpu_image = gpu_images
+        .get(&display_image.0)
+        .ok_or_else(|| String::from("The displayed image is not uploaded yet"))?;
+    let size = SizeSettings {
+        width: gpu_image.size.x as u32,
+        height: gpu_image.size.y as u32,
+    };
+
+    let texels = read_texture(
+        world.resource::<RenderDevice>(),
+        world.resource::<RenderQueue>(),
+        &gpu_image.texture,
+        &size,
+        RGBA16F_TEXEL_SIZE,
+        1,
+    )?;
+
+    Ok(TrailMap::from_rgba16f(size.width, size.height, &texels))
+}
+
+fn finish_recording(recorder: Recorder) {
+    let path = recorder.path().to_path_buf();
+
+    match recorder.finish() {
+        Ok(frames) => info!("Recorded {} frames to {}", frames, path.display()),
+        Err(error) => error!("{}", error),
+    }
+}
+
+/// A restored snapshot continues from its own clock, anything else starts from zero
+fn restored_clock(app_restore_snapshot: &AppRestoreSnapshot) -> (f32, u32) {
+    match &app_restore_snapshot.0 {
+        Some(snapshot) => (snapshot.time, snapshot.frame),
+        None => (0.0, 0),
+    }
+}
@@ -1,3 +1,5 @@
+use std::{num::NonZeroU32, sync::mpsc};
+
 use bevy::{
     prelude::{FromWorld, World},
     render::{
@@ -5,50 +7,85 @@ use bevy::{
         renderer::{RenderContext, RenderDevice, RenderQueue},
     },
 };
-use copy_pipeline::CopyPipeline;
-use diffuse_pipeline::DiffusePipeline;
-use draw_sensor_pipeline::DrawSensorPipeline;
-use rand::Rng;
+use pass_graph::{ComputePassRegistry, PassContext, PassGraph, PassResources, PassSchedule};
 
 use crate::{
-    compute_render_node::ComputeSlimeState,
+    agent_spawner::{spawn_additional_agents, spawn_agents},
+    compute_render_node::{ComputeSlimeState, PipelineError},
+    pass_timer::PassTimer,
     pipeline::{
-        copy_pipeline::{self, CopyTextureView},
-        diffuse_pipeline::{self, DiffuseBuffers},
-        draw_sensor_pipeline::{self, DrawSensorBuffers},
-        slime_sim_pipeline::{SlimeSimBuffers, SlimeSimSetup},
-        Pipeline, SlimeSimPipeline,
+        pass_graph,
+        resample_pipeline::{ResampleBuffers, ResampleExecute},
+        PingPongSide, Pipeline, ResamplePipeline,
     },
+    snapshot::{Snapshot, TEXEL_SIZE},
     types::{
-        Agent, AgentDistribution, AppSettings, DiffuseSettings, SizeSettings, SlimeSettings,
-        TimeBuffer,
+        Agent, AppSettings, ColorMapSettings, DiffuseSettings, Gradient, SizeSettings,
+        SlimeSettings, TimeBuffer, GRADIENT_SIZE, MAX_KERNEL_WEIGHTS,
     },
+    AppRestoreSnapshot,
 };
 
+/// Upper bound of simulation steps run in a single rendered frame
+pub const MAX_SUBSTEPS: u32 = 32;
+
+/// Upper bound of live agents, keeps the agents buffer under the default storage binding limit
+pub const MAX_AGENTS: u32 = 1 << 22;
+
+const PASS_RESOURCE_NAMES: [&str; 15] = [
+    "size",
+    "slime_settings",
+    "diffuse_settings",
+    "time",
+    "agents",
+    "species",
+    "interactions",
+    "color_map",
+    "species_colors",
+    "display",
+    "next_display",
+    "trail",
+    "next_trail",
+    "blur",
+    "render_display",
+];
+
 pub struct ComputeSlimePipeline {
     pub state: ComputeSlimeState,
 
-    pub copy_render_display_to_display_pipeline: CopyPipeline,
-    pub copy_display_to_trail_pipeline: CopyPipeline,
-    pub slime_sim_pipeline: SlimeSimPipeline,
-    pub diffuse_pipeline: DiffusePipeline,
-    pub copy_diffuse_to_display_pipeline: CopyPipeline,
-    pub copy_display_to_render_display_pipeline: CopyPipeline,
+    pub passes: PassGraph,
+    pass_error: Option<PipelineError>,
 
-    pub copy_display_to_sensor_pipeline: CopyPipeline,
-    pub draw_sensor_pipeline: DrawSensorPipeline,
-    pub copy_sensor_to_render_display_pipeline: CopyPipeline,
+    pub resample_pipeline: ResamplePipeline,
 
     pub agents_buffer: Buffer,
     pub size_buffer: Buffer,
     pub diffuse_buffer: Buffer,
     pub settings_buffer: Buffer,
     pub time_buffer: Buffer,
-
-    pub display_texture_view: TextureView,
-    pub trail_texture_view: TextureView,
-    pub diffuse_texture_view: TextureView,
-    pub sensor_texture_view: TextureView,
+    pub time_steps_buffer: Buffer,
+    pub species_buffer: Buffer,
+    /// Row after row, the weight each species gives to the trail of every species when sensing
+    pub interactions_buffer: Buffer,
+    pub color_map_buffer: Buffer,
+    pub species_colors_buffer: Buffer,
+
+    pub num_species: usize,
+    /// Agents simulated, the start of the agents buffer
+    pub num_agents: u32,
+    /// Agents the agents buffer can hold before it has to be reallocated
+    pub agents_capacity: u32,
+    pub size_settings: SizeSettings,
+
+    /// Ping-pong pairs, each step senses the display texture of one side and deposits onto the
+    /// trail texture of the same side, which holds the same trails. The diffuse pass writes both
+    /// textures of the other side
+    pub display_textures: [Texture; 2],
+    pub display_texture_views: [TextureView; 2],
+    pub trail_texture_views: [TextureView; 2],
+    /// Side holding the trails of the last steps, read back for snapshots
+    pub side: PingPongSide,
+    pub blur_texture_view: TextureView,
 }
 
 pub struct ComputeSlimeBindGroup<'a> {
@@ -56,15 +93,21 @@ pub struct ComputeSlimeBindGroup<'a> {
 }
 
 pub struct ComputeTimeUpdate {
-    pub time_buffer: TimeBuffer,
+    /// One time buffer per simulation step of the frame
+    pub time_buffers: Vec<TimeBuffer>,
+}
+
+pub struct ComputeSlimeExecute<'a> {
+    pub steps: u32,
+    /// Side the first step reads, the steps swap it in turn
+    pub side: PingPongSide,
+    pub render_sensors: bool,
+    pub timer: &'a PassTimer,
 }
 
 impl ComputeSlimePipeline {
     pub fn update_settings(&self, queue: &RenderQueue, app_settings: &AppSettings) {
-        let diffuse_settings = DiffuseSettings {
-            decay_rate: app_settings.decay_rate,
-            diffuse_rate: app_settings.diffuse_rate,
-        };
+        let diffuse_settings = diffuse_settings(app_settings);
 
         queue.write_buffer(
             &self.diffuse_buffer,
@@ -73,11 +116,10 @@ impl ComputeSlimePipeline {
         );
 
         let slime_settings = SlimeSettings {
-            num_agents: app_settings.num_agents,
+            num_agents: self.num_agents,
             trail_weight: app_settings.trail_weight,
-            memory_offset_1: 0,
-            memory_offset_2: 0,
-            species_settings: app_settings.species_settings,
+            boundary_mode: app_settings.boundary_mode as u32,
+            num_species: self.num_species as u32,
         };
 
         queue.write_buffer(
@@ -85,130 +127,370 @@ impl ComputeSlimePipeline {
             0,
             bytemuck::bytes_of(&slime_settings),
         );
+
+        // the species buffers are sized at creation, a reset is pending when the count differs
+        if app_settings.num_species() == self.num_species {
+            queue.write_buffer(
+                &self.species_buffer,
+                0,
+                bytemuck::cast_slice(&app_settings.species_settings),
+            );
+            queue.write_buffer(
+                &self.interactions_buffer,
+                0,
+                bytemuck::cast_slice(&species_interactions(app_settings)),
+            );
+            queue.write_buffer(
+                &self.species_colors_buffer,
+                0,
+                bytemuck::cast_slice(&species_colors(app_settings)),
+            );
+        }
+
+        queue.write_buffer(
+            &self.color_map_buffer,
+            0,
+            bytemuck::bytes_of(&color_map_settings(app_settings)),
+        );
+    }
+
+    /// Follows `app_settings.num_agents` without touching the running agents nor the trails, new
+    /// agents are appended and removed agents are dropped from the end of the buffer
+    pub fn resize_agents(
+        &mut self,
+        render_device: &RenderDevice,
+        queue: &RenderQueue,
+        app_settings: &AppSettings,
+        spawn_cursor: Option<[f32; 2]>,
+    ) {
+        let num_agents = app_settings.num_agents.clamp(1, MAX_AGENTS);
+        if num_agents == self.num_agents {
+            return;
+        }
+
+        let agent_size = std::mem::size_of::<Agent>() as u64;
+
+        if num_agents > self.agents_capacity {
+            // grow geometrically so dragging the count up does not reallocate every frame
+            let agents_capacity = num_agents.max(self.agents_capacity * 2).min(MAX_AGENTS);
+
+            let agents_buffer = render_device.create_buffer(&BufferDescriptor {
+                label: Some("Agents buffer"),
+                size: agent_size * agents_capacity as u64,
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+
+            let mut command_encoder =
+                render_device.create_command_encoder(&CommandEncoderDescriptor {
+                    label: Some("Grow agents buffer"),
+                });
+            command_encoder.copy_buffer_to_buffer(
+                &self.agents_buffer,
+                0,
+                &agents_buffer,
+                0,
+                agent_size * self.num_agents as u64,
+            );
+            queue.submit([command_encoder.finish()]);
+
+            self.agents_buffer = agents_buffer;
+            self.agents_capacity = agents_capacity;
+        }
+
+        if num_agents > self.num_agents {
+            let agents = spawn_additional_agents(
+                app_settings,
+                self.num_agents,
+                num_agents - self.num_agents,
+                spawn_cursor,
+            );
+
+            queue.write_buffer(
+                &self.agents_buffer,
+                agent_size * self.num_agents as u64,
+                bytemuck::cast_slice(&agents),
+            );
+        }
+
+        self.num_agents = num_agents;
+        self.update_settings(queue, app_settings);
+    }
+
+    /// Reallocates the simulation textures at `app_settings` size, the trails are resampled into
+    /// the new textures and the agents moved to the same relative place when asked to
+    pub fn resize(
+        &mut self,
+        render_device: &RenderDevice,
+        queue: &RenderQueue,
+        pipeline_cache: &PipelineCache,
+        app_settings: &AppSettings,
+    ) {
+        let size_settings = SizeSettings {
+            width: app_settings.width.max(1),
+            height: app_settings.height.max(1),
+        };
+
+        if size_settings.width == self.size_settings.width
+            && size_settings.height == self.size_settings.height
+        {
+            return;
+        }
+
+        let old_size_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("Old size buffer"),
+            contents: bytemuck::bytes_of(&self.size_settings),
+            usage: BufferUsages::UNIFORM,
+        });
+        // written before the resample pass is submitted
+        queue.write_buffer(&self.size_buffer, 0, bytemuck::bytes_of(&size_settings));
+
+        // the resampled trails start again from the first side
+        let layers = self.num_species as u32;
+        self.display_textures = create_simulation_textures(render_device, &size_settings, layers);
+        let old_display_texture_views = std::mem::replace(
+            &mut self.display_texture_views,
+            create_texture_views(&self.display_textures),
+        );
+        let old_side = std::mem::replace(&mut self.side, 0);
+        self.trail_texture_views = create_texture_views(&create_simulation_textures(
+            render_device,
+            &size_settings,
+            layers,
+        ));
+        self.blur_texture_view = create_blur_texture_view(render_device, &size_settings, layers);
+
+        self.passes.set_size(&size_settings);
+
+        self.size_settings = size_settings;
+
+        let resample_settings = ResampleExecute {
+            new_size: size_settings,
+            num_agents: self.num_agents,
+            resample_trails: app_settings.resample_on_resize,
+            scale_agents: app_settings.scale_agents_on_resize,
+        };
+
+        if !(resample_settings.resample_trails || resample_settings.scale_agents) {
+            return;
+        }
+
+        let num_agents_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("Agent count buffer"),
+            contents: bytemuck::bytes_of(&self.num_agents),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        self.resample_pipeline.queue_bind_group(
+            render_device,
+            &ResampleBuffers {
+                old_size_buffer,
+                new_size_buffer: self.size_buffer.clone(),
+                agents_buffer: self.agents_buffer.clone(),
+                num_agents_buffer,
+                texture_view_read: &old_display_texture_views[old_side],
+                texture_view_write: &self.display_texture_views[0],
+                trail_texture_view_write: &self.trail_texture_views[0],
+            },
+        );
+
+        let mut render_context = RenderContext {
+            render_device: render_device.clone(),
+            command_encoder: render_device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("Resize simulation"),
+            }),
+        };
+        self.resample_pipeline.execute(
+            &mut render_context,
+            pipeline_cache,
+            &self.state,
+            &resample_settings,
+        );
+        queue.submit([render_context.command_encoder.finish()]);
+    }
+
+    /// Copies the agents and the display texture back from the GPU, blocking until they are read
+    pub fn read_snapshot(
+        &self,
+        render_device: &RenderDevice,
+        queue: &RenderQueue,
+        app_settings: &AppSettings,
+        time: f32,
+        frame: u32,
+    ) -> Result<Snapshot, String> {
+        let agents_size = (std::mem::size_of::<Agent>() * self.num_agents as usize) as u64;
+
+        let agents_readback = render_device.create_buffer(&BufferDescriptor {
+            label: Some("Agents readback buffer"),
+            size: agents_size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut command_encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Read agents"),
+        });
+        command_encoder.copy_buffer_to_buffer(
+            &self.agents_buffer,
+            0,
+            &agents_readback,
+            0,
+            agents_size,
+        );
+        queue.submit([command_encoder.finish()]);
+
+        let agents: Vec<Agent> = read_buffer(render_device, &agents_readback, |bytes| {
+            bytemuck::cast_slice(bytes).to_vec()
+        })?;
+        let display = read_texture(
+            render_device,
+            queue,
+            &self.display_textures[self.side],
+            &self.size_settings,
+            TEXEL_SIZE,
+            self.num_species as u32,
+        )?;
+
+        Ok(Snapshot {
+            app_settings: AppSettings {
+                width: self.size_settings.width,
+                height: self.size_settings.height,
+                num_agents: self.num_agents,
+                ..app_settings.clone()
+            },
+            time,
+            frame,
+            agents,
+            display,
+        })
     }
 
     pub fn update_time(&self, queue: &RenderQueue, update: &ComputeTimeUpdate) {
+        if update.time_buffers.is_empty() {
+            return;
+        }
+
         queue.write_buffer(
-            &self.time_buffer,
+            &self.time_steps_buffer,
             0,
-            bytemuck::bytes_of(&update.time_buffer),
+            bytemuck::cast_slice(&update.time_buffers),
         );
     }
 
+    /// Loaded once every pipeline is compiled, in error as soon as one of them fails to. A shader
+    /// changed on disk compiles its pipelines again, the simulation waits for them while reloading.
+    /// An error stays until the shader is fixed and every pipeline compiled, one of the passes
+    /// themselves stays until the pipeline is created again
     pub fn update_state(&self, pipeline_cache: &PipelineCache, state: &mut ComputeSlimeState) {
-        match state {
-            ComputeSlimeState::Init => {
-                if let (
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                    CachedPipelineState::Ok(_),
-                ) = (
-                    pipeline_cache.get_compute_pipeline_state(
-                        self.copy_render_display_to_display_pipeline
-                            .copy_pipeline_id,
-                    ),
-                    pipeline_cache.get_compute_pipeline_state(
-                        self.copy_display_to_trail_pipeline.copy_pipeline_id,
-                    ),
-                    pipeline_cache
-                        .get_compute_pipeline_state(self.slime_sim_pipeline.slime_sim_pipeline_id),
-                    pipeline_cache
-                        .get_compute_pipeline_state(self.diffuse_pipeline.diffuse_pipeline_id),
-                    pipeline_cache.get_compute_pipeline_state(
-                        self.copy_diffuse_to_display_pipeline.copy_pipeline_id,
-                    ),
-                    pipeline_cache.get_compute_pipeline_state(
-                        self.copy_display_to_render_display_pipeline
-                            .copy_pipeline_id,
-                    ),
-                    pipeline_cache.get_compute_pipeline_state(
-                        self.copy_display_to_sensor_pipeline.copy_pipeline_id,
-                    ),
-                    pipeline_cache.get_compute_pipeline_state(
-                        self.draw_sensor_pipeline.draw_sensor_pipeline_id,
-                    ),
-                    pipeline_cache.get_compute_pipeline_state(
-                        self.copy_sensor_to_render_display_pipeline.copy_pipeline_id,
+        if let Some(error) = &self.pass_error {
+            *state = ComputeSlimeState::Error(error.clone());
+            return;
+        }
+
+        let pipeline_ids = self.pipeline_ids();
+
+        let error = pipeline_ids.iter().find_map(|&(pass, pipeline_id)| {
+            match pipeline_cache.get_compute_pipeline_state(pipeline_id) {
+                // retried by the pipeline cache once the shaders are loaded
+                CachedPipelineState::Err(
+                    PipelineCacheError::ShaderNotLoaded(_)
+                    | PipelineCacheError::ShaderImportNotYetAvailable,
+                ) => None,
+                CachedPipelineState::Err(error) => Some(PipelineError {
+                    pass,
+                    entry_point: Some(
+                        pipeline_cache
+                            .get_compute_pipeline_descriptor(pipeline_id)
+                            .entry_point
+                            .to_string(),
                     ),
-                ) {
-                    *state = ComputeSlimeState::Loaded;
-                }
+                    message: pipeline_error_message(error),
+                }),
+                _ => None,
             }
-            ComputeSlimeState::Loaded => {}
+        });
+
+        if let Some(error) = error {
+            *state = ComputeSlimeState::Error(error);
+            return;
         }
+
+        let loaded = pipeline_ids.iter().all(|&(_, pipeline_id)| {
+            matches!(
+                pipeline_cache.get_compute_pipeline_state(pipeline_id),
+                CachedPipelineState::Ok(_)
+            )
+        });
+
+        match state {
+            ComputeSlimeState::Init
+            | ComputeSlimeState::Reloading
+            | ComputeSlimeState::Error(_)
+                if loaded =>
+            {
+                *state = ComputeSlimeState::Loaded;
+            }
+            ComputeSlimeState::Loaded if !loaded => *state = ComputeSlimeState::Reloading,
+            _ => {}
+        }
+    }
+
+    pub fn pipeline_ids(&self) -> Vec<(&'static str, CachedComputePipelineId)> {
+        let mut pipeline_ids = vec![
+            ("resample", self.resample_pipeline.resample_pipeline_id),
+            ("resample", self.resample_pipeline.scale_agents_pipeline_id),
+        ];
+        pipeline_ids.extend(self.passes.pipeline_ids());
+
+        pipeline_ids
+    }
+
+    /// Buffers and textures the passes bind by name. `display` and `trail` are the ping-pong
+    /// textures of the current side, `next_display` and `next_trail` the ones of the other side
+    fn pass_resources(&self, render_display: &TextureView) -> PassResources {
+        let mut resources = PassResources::default();
+
+        resources.insert_buffer("size", self.size_buffer.clone());
+        resources.insert_buffer("slime_settings", self.settings_buffer.clone());
+        resources.insert_buffer("diffuse_settings", self.diffuse_buffer.clone());
+        resources.insert_buffer("time", self.time_buffer.clone());
+        resources.insert_buffer("agents", self.agents_buffer.clone());
+        resources.insert_buffer("species", self.species_buffer.clone());
+        resources.insert_buffer("interactions", self.interactions_buffer.clone());
+        resources.insert_buffer("color_map", self.color_map_buffer.clone());
+        resources.insert_buffer("species_colors", self.species_colors_buffer.clone());
+
+        resources.insert_ping_pong(
+            "display",
+            "next_display",
+            self.display_texture_views.clone(),
+        );
+        resources.insert_ping_pong("trail", "next_trail", self.trail_texture_views.clone());
+        resources.insert_texture("blur", self.blur_texture_view.clone());
+        resources.insert_texture("render_display", render_display.clone());
+        debug_assert!(PASS_RESOURCE_NAMES
+            .iter()
+            .all(|name| resources.contains(name)));
+
+        resources
     }
 }
 
 impl<'a> Pipeline<'a> for ComputeSlimePipeline {
     type CreationSettings = AppSettings;
     type BindGroupSettings = ComputeSlimeBindGroup<'a>;
-    type ExecuteSettings = AppSettings;
+    type ExecuteSettings = ComputeSlimeExecute<'a>;
 
     fn new(world: &mut World) -> Self {
         let render_device = world.resource::<RenderDevice>();
+        let render_queue = world.resource::<RenderQueue>();
         let app_settings = world.resource::<AppSettings>();
+        let app_restore_snapshot = world.resource::<AppRestoreSnapshot>();
 
-        let agents: Vec<Agent> = match app_settings.agent_distribution {
-            AgentDistribution::InnerCircle => (0..app_settings.num_agents)
-                .into_iter()
-                .map(|i| {
-                    let angle: f32 = 2.0 * 3.1415 / (app_settings.num_agents as f32) * i as f32;
-                    let x = app_settings.width as f32 / 2.0 + angle.cos() * 200.0;
-                    let y = app_settings.height as f32 / 2.0 + angle.sin() * 200.0;
-                    Agent {
-                        position: [x, y],
-                        angle: 3.1415 + angle,
-                        species_index: 0,
-                    }
-                })
-                .collect::<Vec<_>>(),
-            AgentDistribution::OuterCircle => (0..app_settings.num_agents)
-                .into_iter()
-                .map(|i| {
-                    let angle: f32 = 2.0 * 3.1415 / (app_settings.num_agents as f32) * i as f32;
-                    let x = app_settings.width as f32 / 2.0 + angle.cos() * 200.0;
-                    let y = app_settings.height as f32 / 2.0 + angle.sin() * 200.0;
-                    Agent {
-                        position: [x, y],
-                        angle: angle,
-                        species_index: 0,
-                    }
-                })
-                .collect::<Vec<_>>(),
-            AgentDistribution::InnerDisk => (0..app_settings.num_agents)
-                .into_iter()
-                .map(|_| {
-                    let angle: f32 = rand::thread_rng().gen::<f32>() * 2.0 * 3.1415;
-                    let x = app_settings.width as f32 / 2.0
-                        + angle.cos() * rand::thread_rng().gen::<f32>() * 200.0;
-                    let y = app_settings.height as f32 / 2.0
-                        + angle.sin() * rand::thread_rng().gen::<f32>() * 200.0;
-                    Agent {
-                        position: [x, y],
-                        angle: angle + 3.1415,
-                        species_index: 0,
-                    }
-                })
-                .collect::<Vec<_>>(),
-            AgentDistribution::Random => (0..app_settings.num_agents)
-                .into_iter()
-                .map(|_| {
-                    let angle: f32 = rand::thread_rng().gen::<f32>() * 2.0 * 3.1415;
-                    let x = rand::thread_rng().gen::<f32>() * app_settings.width as f32;
-                    let y = rand::thread_rng().gen::<f32>() * app_settings.height as f32;
-                    Agent {
-                        position: [x, y],
-                        angle: angle,
-                        species_index: 0,
-                    }
-                })
-                .collect::<Vec<_>>(),
+        // a restored snapshot carries the agents and the trails, its settings are already applied
+        let agents = match &app_restore_snapshot.0 {
+            Some(snapshot) => snapshot.agents.clone(),
+            None => spawn_agents(app_settings),
         };
 
         let size_settings = SizeSettings {
@@ -216,29 +498,28 @@ impl<'a> Pipeline<'a> for ComputeSlimePipeline {
             height: app_settings.height,
         };
 
-        let diffuse_settings = DiffuseSettings {
-            decay_rate: app_settings.decay_rate,
-            diffuse_rate: app_settings.diffuse_rate,
-        };
+        let diffuse_settings = diffuse_settings(app_settings);
 
         let slime_settings = SlimeSettings {
             num_agents: app_settings.num_agents,
             trail_weight: app_settings.trail_weight,
-            memory_offset_1: 0,
-            memory_offset_2: 0,
-
-            species_settings: app_settings.species_settings,
+            boundary_mode: app_settings.boundary_mode as u32,
+            num_species: app_settings.num_species() as u32,
         };
 
+        let color_map_settings = color_map_settings(app_settings);
+
         let time = TimeBuffer {
             time: 0.0,
             delta_time: 0.0,
+            frame: 0,
+            seed: shader_seed(app_settings.seed),
         };
 
         let agents_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
             label: Some("Agents buffer"),
             contents: bytemuck::cast_slice(&agents),
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
         });
 
         let size_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
@@ -265,211 +546,122 @@ impl<'a> Pipeline<'a> for ComputeSlimePipeline {
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
-        let display_texture = render_device.create_texture(&TextureDescriptor {
-            label: None,
-            size: Extent3d {
-                width: app_settings.width,
-                height: app_settings.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba16Float,
-            usage: TextureUsages::TEXTURE_BINDING
-                | TextureUsages::COPY_DST
-                | TextureUsages::STORAGE_BINDING,
+        // every step of a frame copies its own time into the time buffer before running
+        let time_steps_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("Time steps buffer"),
+            size: (std::mem::size_of::<TimeBuffer>() as u32 * MAX_SUBSTEPS) as u64,
+            usage: BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
-        let display_texture_view = display_texture.create_view(&TextureViewDescriptor::default());
+        let species_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("Species buffer"),
+            contents: bytemuck::cast_slice(&app_settings.species_settings),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
 
-        let trail_texture = render_device.create_texture(&TextureDescriptor {
-            label: None,
-            size: Extent3d {
-                width: app_settings.width,
-                height: app_settings.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba16Float,
-            usage: TextureUsages::TEXTURE_BINDING
-                | TextureUsages::COPY_DST
-                | TextureUsages::STORAGE_BINDING,
+        let interactions_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("Interactions buffer"),
+            contents: bytemuck::cast_slice(&species_interactions(app_settings)),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
         });
 
-        let trail_texture_view = trail_texture.create_view(&TextureViewDescriptor::default());
+        let color_map_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("Color map buffer"),
+            contents: bytemuck::bytes_of(&color_map_settings),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
 
-        let diffuse_texture = render_device.create_texture(&TextureDescriptor {
-            label: None,
-            size: Extent3d {
-                width: app_settings.width,
-                height: app_settings.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba16Float,
-            usage: TextureUsages::TEXTURE_BINDING
-                | TextureUsages::COPY_DST
-                | TextureUsages::STORAGE_BINDING,
+        let species_colors_buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("Species colors buffer"),
+            contents: bytemuck::cast_slice(&species_colors(app_settings)),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
         });
 
-        let diffuse_texture_view = diffuse_texture.create_view(&TextureViewDescriptor::default());
+        let num_species = app_settings.num_species();
+        let layers = num_species as u32;
+        let display_textures = create_simulation_textures(render_device, &size_settings, layers);
+        let trail_textures = create_simulation_textures(render_device, &size_settings, layers);
+
+        // the first steps sense and deposit onto the first side
+        if let Some(snapshot) = &app_restore_snapshot.0 {
+            for texture in [&display_textures[0], &trail_textures[0]] {
+                render_queue.write_texture(
+                    texture.as_image_copy(),
+                    &snapshot.display,
+                    ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(size_settings.width * TEXEL_SIZE as u32),
+                        rows_per_image: NonZeroU32::new(size_settings.height),
+                    },
+                    Extent3d {
+                        width: size_settings.width,
+                        height: size_settings.height,
+                        depth_or_array_layers: layers,
+                    },
+                );
+            }
+        }
 
-        let sensor_texture = render_device.create_texture(&TextureDescriptor {
-            label: None,
-            size: Extent3d {
-                width: app_settings.width,
-                height: app_settings.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba16Float,
-            usage: TextureUsages::TEXTURE_BINDING
-                | TextureUsages::COPY_DST
-                | TextureUsages::STORAGE_BINDING,
-        });
+        let display_texture_views = create_texture_views(&display_textures);
+        let trail_texture_views = create_texture_views(&trail_textures);
 
-        let sensor_texture_view = sensor_texture.create_view(&TextureViewDescriptor::default());
+        let blur_texture_view = create_blur_texture_view(render_device, &size_settings, layers);
 
-        let slime_sim_setup = SlimeSimSetup {
-            num_agents: app_settings.num_agents,
-        };
+        let num_agents = agents.len() as u32;
 
-        world.insert_resource(slime_sim_setup);
         world.insert_resource(size_settings);
 
-        let copy_render_display_to_display_pipeline = CopyPipeline::new(world);
-        let copy_display_to_trail_pipeline = CopyPipeline::new(world);
-        let slime_sim_pipeline = SlimeSimPipeline::new(world);
-        let diffuse_pipeline = DiffusePipeline::new(world);
-        let copy_diffuse_to_display_pipeline = CopyPipeline::new(world);
-        let copy_display_to_render_display_pipeline = CopyPipeline::new(world);
+        let registry = world.resource::<ComputePassRegistry>().clone();
+        let passes = registry.build(world);
+        let (passes, pass_error) = match PassGraph::new(passes, &PASS_RESOURCE_NAMES) {
+            Ok(passes) => (passes, None),
+            Err(error) => (PassGraph::default(), Some(error)),
+        };
 
-        let copy_display_to_sensor_pipeline = CopyPipeline::new(world);
-        let draw_sensor_pipeline = DrawSensorPipeline::new(world);
-        let copy_sensor_to_render_display_pipeline = CopyPipeline::new(world);
+        let resample_pipeline = ResamplePipeline::new(world);
 
         Self {
             state: ComputeSlimeState::Init,
 
-            copy_render_display_to_display_pipeline,
-            copy_display_to_trail_pipeline,
-            slime_sim_pipeline,
-            diffuse_pipeline,
-            copy_diffuse_to_display_pipeline,
-            copy_display_to_render_display_pipeline,
+            passes,
+            pass_error,
 
-            copy_display_to_sensor_pipeline,
-            draw_sensor_pipeline,
-            copy_sensor_to_render_display_pipeline,
+            resample_pipeline,
 
             agents_buffer,
             size_buffer,
             diffuse_buffer,
             settings_buffer,
             time_buffer,
-
-            display_texture_view,
-            trail_texture_view,
-            diffuse_texture_view,
-            sensor_texture_view,
+            time_steps_buffer,
+            species_buffer,
+            interactions_buffer,
+            color_map_buffer,
+            species_colors_buffer,
+
+            num_species,
+            num_agents,
+            agents_capacity: num_agents,
+            size_settings,
+
+            display_textures,
+            display_texture_views,
+            trail_texture_views,
+            side: 0,
+            blur_texture_view,
         }
     }
 
     fn queue_bind_group(&mut self, render_device: &RenderDevice, settings: &ComputeSlimeBindGroup) {
-        let copy_render_display_to_display = CopyTextureView {
-            size_buffer: self.size_buffer.clone(),
-            texture_view_read: settings.display_texture_view,
-            texture_view_write: &self.display_texture_view,
-        };
-
-        self.copy_render_display_to_display_pipeline
-            .queue_bind_group(render_device, &copy_render_display_to_display);
-
-        let copy_display_to_trail = CopyTextureView {
-            size_buffer: self.size_buffer.clone(),
-            texture_view_read: &self.display_texture_view,
-            texture_view_write: &self.trail_texture_view,
-        };
-
-        self.copy_display_to_trail_pipeline
-            .queue_bind_group(render_device, &copy_display_to_trail);
-
-        let slime_sim_buffers = SlimeSimBuffers {
-            size_buffers: self.size_buffer.clone(),
-            agents_buffer: self.agents_buffer.clone(),
-            settings_buffer: self.settings_buffer.clone(),
-            time_buffer: self.time_buffer.clone(),
-            texture_view_read: &self.display_texture_view,
-            texture_view_write: &self.trail_texture_view,
-        };
-
-        self.slime_sim_pipeline
-            .queue_bind_group(render_device, &slime_sim_buffers);
-
-        let diffuse_buffers = DiffuseBuffers {
-            size_buffer: self.size_buffer.clone(),
-            diffuse_buffer: self.diffuse_buffer.clone(),
-            time_buffer: self.time_buffer.clone(),
-            trail_texture: &self.trail_texture_view,
-            diffuse_texture: &self.diffuse_texture_view,
-        };
-
-        self.diffuse_pipeline
-            .queue_bind_group(render_device, &diffuse_buffers);
-
-        let copy_diffuse_to_display = CopyTextureView {
-            size_buffer: self.size_buffer.clone(),
-            texture_view_read: &self.diffuse_texture_view,
-            texture_view_write: &self.display_texture_view,
-        };
-
-        self.copy_diffuse_to_display_pipeline
-            .queue_bind_group(render_device, &copy_diffuse_to_display);
-
-        let copy_display_to_render_display = CopyTextureView {
-            size_buffer: self.size_buffer.clone(),
-            texture_view_read: &self.display_texture_view,
-            texture_view_write: settings.display_texture_view,
-        };
-
-        self.copy_display_to_render_display_pipeline
-            .queue_bind_group(render_device, &copy_display_to_render_display);
-
-        let copy_display_to_sensor = CopyTextureView {
-            size_buffer: self.size_buffer.clone(),
-            texture_view_read: &self.display_texture_view,
-            texture_view_write: &self.sensor_texture_view,
-        };
-
-        self.copy_display_to_sensor_pipeline
-            .queue_bind_group(render_device, &copy_display_to_sensor);
-
-        let draw_sensor = DrawSensorBuffers {
-            size_buffers: self.size_buffer.clone(),
-            settings_buffer: self.settings_buffer.clone(),
-            agents_buffer: self.agents_buffer.clone(),
-            texture_view_read: &self.display_texture_view,
-            texture_view_write: &self.sensor_texture_view,
-        };
-
-        self.draw_sensor_pipeline
-            .queue_bind_group(render_device, &draw_sensor);
-
-        let copy_sensor_to_render_display = CopyTextureView {
-            size_buffer: self.size_buffer.clone(),
-            texture_view_read: &self.sensor_texture_view,
-            texture_view_write: settings.display_texture_view,
-        };
+        if self.pass_error.is_some() {
+            return;
+        }
 
-        self.copy_sensor_to_render_display_pipeline
-            .queue_bind_group(render_device, &copy_sensor_to_render_display);
+        let resources = self.pass_resources(settings.display_texture_view);
+        if let Err(error) = self.passes.queue_bind_groups(render_device, &resources) {
+            self.pass_error = Some(error);
+        }
     }
 
     fn execute(
@@ -477,60 +669,56 @@ impl<'a> Pipeline<'a> for ComputeSlimePipeline {
         render_context: &mut RenderContext,
         pipeline_cache: &PipelineCache,
         state: &ComputeSlimeState,
-        app_settings: &AppSettings,
+        execute_settings: &ComputeSlimeExecute<'a>,
     ) {
+        let timer = execute_settings.timer;
+
         match state {
-            ComputeSlimeState::Init => {
-                self.copy_render_display_to_display_pipeline.execute(
-                    render_context,
-                    pipeline_cache,
-                    state,
-                    &(),
-                );
-            }
+            // new textures start blank, a restored snapshot is written into them at creation
+            ComputeSlimeState::Init
+            | ComputeSlimeState::Reloading
+            | ComputeSlimeState::Error(_) => {}
             ComputeSlimeState::Loaded => {
-                self.copy_display_to_trail_pipeline.execute(
-                    render_context,
-                    pipeline_cache,
-                    state,
-                    &(),
-                );
-                self.slime_sim_pipeline
-                    .execute(render_context, pipeline_cache, state, &());
-                self.diffuse_pipeline
-                    .execute(render_context, pipeline_cache, state, &());
-                self.copy_diffuse_to_display_pipeline.execute(
-                    render_context,
-                    pipeline_cache,
-                    state,
-                    &(),
-                );
-
-                if app_settings.render_sensors {
-                    self.copy_display_to_sensor_pipeline.execute(
-                        render_context,
-                        pipeline_cache,
-                        state,
-                        &(),
+                let time_size = std::mem::size_of::<TimeBuffer>() as u64;
+                let mut context = PassContext {
+                    side: execute_settings.side,
+                    num_agents: self.num_agents,
+                    render_sensors: execute_settings.render_sensors,
+                };
+
+                for step in 0..execute_settings.steps.min(MAX_SUBSTEPS) {
+                    render_context.command_encoder.copy_buffer_to_buffer(
+                        &self.time_steps_buffer,
+                        step as u64 * time_size,
+                        &self.time_buffer,
+                        0,
+                        time_size,
                     );
-                    self.draw_sensor_pipeline
-                        .execute(render_context, pipeline_cache, state, &());
-                    self.copy_sensor_to_render_display_pipeline.execute(
-                        render_context,
-                        pipeline_cache,
-                        state,
-                        &(),
-                    );
-                } else {
-                    self.copy_display_to_render_display_pipeline.execute(
+
+                    self.passes.execute(
+                        PassSchedule::Step,
                         render_context,
                         pipeline_cache,
                         state,
-                        &(),
+                        &context,
+                        timer,
                     );
+
+                    context.side = 1 - context.side;
                 }
+
+                self.passes.execute(
+                    PassSchedule::Frame,
+                    render_context,
+                    pipeline_cache,
+                    state,
+                    &context,
+                    timer,
+                );
             }
         }
+
+        timer.end_frame(render_context);
     }
 }
 
@@ -539,3 +727,223 @@ impl FromWorld for ComputeSlimePipeline {
         ComputeSlimePipeline::new(world)
     }
 }
+
+/// The error with its causes, WGSL parse errors point at the shader lines
+fn pipeline_error_message(error: &PipelineCacheError) -> String {
+    if let PipelineCacheError::AsModuleDescriptorError(
+        AsModuleDescriptorError::ShaderReflectError(ShaderReflectError::WgslParse(error)),
+        ProcessedShader::Wgsl(source),
+    ) = error
+    {
+        return error.emit_to_string(source);
+    }
+
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        message.push_str(&format!("\n{}", error));
+        source = error.source();
+    }
+
+    message
+}
+
+fn create_blur_texture_view(
+    render_device: &RenderDevice,
+    size: &SizeSettings,
+    layers: u32,
+) -> TextureView {
+    create_simulation_texture(render_device, size, layers).create_view(&TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..Default::default()
+    })
+}
+
+/// One texture per ping-pong side
+fn create_simulation_textures(
+    render_device: &RenderDevice,
+    size: &SizeSettings,
+    layers: u32,
+) -> [Texture; 2] {
+    [0, 1].map(|_| create_simulation_texture(render_device, size, layers))
+}
+
+/// A single layer texture would get a 2D view by default
+fn create_texture_views(textures: &[Texture; 2]) -> [TextureView; 2] {
+    [0, 1].map(|side| {
+        textures[side].create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        })
+    })
+}
+
+fn create_simulation_texture(
+    render_device: &RenderDevice,
+    size: &SizeSettings,
+    layers: u32,
+) -> Texture {
+    render_device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::R32Float,
+        usage: TextureUsages::TEXTURE_BINDING
+            | TextureUsages::COPY_DST
+            | TextureUsages::COPY_SRC
+            | TextureUsages::STORAGE_BINDING,
+    })
+}
+
+/// Copies a texture back from the GPU, rows are returned without padding and layer after layer
+pub fn read_texture(
+    render_device: &RenderDevice,
+    queue: &RenderQueue,
+    texture: &Texture,
+    size: &SizeSettings,
+    texel_size: usize,
+    layers: u32,
+) -> Result<Vec<u8>, String> {
+    let row_size = size.width as usize * texel_size;
+    let rows = size.height as usize * layers as usize;
+    // texture copies need rows aligned to 256 bytes
+    let padded_row_size = RenderDevice::align_copy_bytes_per_row(row_size);
+
+    let readback = render_device.create_buffer(&BufferDescriptor {
+        label: Some("Texture readback buffer"),
+        size: (padded_row_size * rows) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Read texture"),
+    });
+    command_encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &readback,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row_size as u32),
+                rows_per_image: NonZeroU32::new(size.height),
+            },
+        },
+        Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: layers,
+        },
+    );
+    queue.submit([command_encoder.finish()]);
+
+    read_buffer(render_device, &readback, |bytes| {
+        bytes
+            .chunks_exact(padded_row_size)
+            .flat_map(|row| &row[..row_size])
+            .copied()
+            .collect()
+    })
+}
+
+fn read_buffer<T>(
+    render_device: &RenderDevice,
+    buffer: &Buffer,
+    convert: impl FnOnce(&[u8]) -> T,
+) -> Result<T, String> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    render_device.map_buffer(&slice, MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    render_device.poll(wgpu::Maintain::Wait);
+
+    receiver
+        .recv()
+        .map_err(|error| error.to_string())?
+        .map_err(|error| format!("Cannot read back GPU buffer: {}", error))?;
+
+    let value = convert(&slice.get_mapped_range());
+    buffer.unmap();
+    Ok(value)
+}
+
+/// Folds the 64 bits seed into the 32 bits hashed by the shaders
+pub fn shader_seed(seed: u64) -> u32 {
+    (seed ^ (seed >> 32)) as u32
+}
+
+pub fn species_interactions(app_settings: &AppSettings) -> Vec<f32> {
+    let num_species = app_settings.num_species();
+
+    (0..num_species)
+        .flat_map(|row| {
+            let interactions = app_settings.species_interactions.get(row);
+            (0..num_species).map(move |column| {
+                interactions
+                    .and_then(|interactions| interactions.get(column))
+                    .copied()
+                    .unwrap_or(0.0)
+            })
+        })
+        .collect()
+}
+
+pub fn diffuse_settings(app_settings: &AppSettings) -> DiffuseSettings {
+    let kernel = app_settings.diffusion_kernel.weights();
+    let mut kernel_weights = [[0.0; 4]; MAX_KERNEL_WEIGHTS / 4];
+
+    for (index, weight) in kernel.weights.iter().take(MAX_KERNEL_WEIGHTS).enumerate() {
+        kernel_weights[index / 4][index % 4] = *weight;
+    }
+
+    DiffuseSettings {
+        decay_rate: app_settings.decay_rate,
+        diffuse_rate: app_settings.diffuse_rate,
+        boundary_mode: app_settings.boundary_mode as u32,
+        kernel_radius: kernel.radius,
+        kernel_matrix: u32::from(kernel.matrix),
+        memory_offset_1: 0,
+        memory_offset_2: 0,
+        memory_offset_3: 0,
+        kernel_weights,
+    }
+}
+
+pub fn color_map_settings(app_settings: &AppSettings) -> ColorMapSettings {
+    let gradient = app_settings.palette.gradient();
+
+    ColorMapSettings {
+        gradient: gradient
+            .as_ref()
+            .map_or([[0.0; 4]; GRADIENT_SIZE], Gradient::lookup_table),
+        use_gradient: u32::from(gradient.is_some()),
+        intensity_min: app_settings.palette_range[0],
+        intensity_max: app_settings.palette_range[1],
+        gamma: app_settings.palette_gamma,
+    }
+}
+
+/// Display colour of every species trail, a gradient palette is only tinted when there are
+/// several species
+pub fn species_colors(app_settings: &AppSettings) -> Vec<[f32; 4]> {
+    let untinted = app_settings.palette.gradient().is_some() && app_settings.num_species() == 1;
+
+    app_settings
+        .species_colors
+        .iter()
+        .map(|&[red, green, blue]| {
+            if untinted {
+                [1.0; 4]
+            } else {
+                [red, green, blue, 1.0]
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,972 @@
+use crate::{
+    agent_spawner::spawn_agents,
+    compute_slime_pipeline::{
+        color_map_settings, diffuse_settings, species_colors, species_interactions,
+    },
+    snapshot::{Snapshot, TEXEL_SIZE},
+    types::{
+        Agent, AppSettings, BoundaryMode, ColorMapSettings, DiffuseSettings, SizeSettings,
+        SlimeSettings, SpeciesSettings, TimeBuffer, GRADIENT_SIZE,
+    },
+};
+
+/// Truncated pi used by the shaders, kept so both paths turn agents by the same amount
+#[allow(clippy::approx_constant)]
+const SHADER_PI: f32 = 3.1415;
+
+const BOUNDARY_WRAP: u32 = BoundaryMode::Wrap as u32;
+const BOUNDARY_REFLECT: u32 = BoundaryMode::Reflect as u32;
+const BOUNDARY_RESPAWN: u32 = BoundaryMode::Respawn as u32;
+
+/// Bytes of a Rgba16Float texel
+pub const RGBA16F_TEXEL_SIZE: usize = 8;
+
+/// CPU side of a Rgba16Float texture, stored values are rounded to half precision like the GPU does
+#[derive(Clone, Debug)]
+pub struct TrailMap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl TrailMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; (width * height) as usize],
+        }
+    }
+
+    /// Decodes the raw texels of a Rgba16Float texture
+    pub fn from_rgba16f(width: u32, height: u32, texels: &[u8]) -> Self {
+        let pixels = texels
+            .chunks_exact(RGBA16F_TEXEL_SIZE)
+            .map(|texel| {
+                [0, 1, 2, 3].map(|channel| {
+                    half_to_f32(u16::from_le_bytes([
+                        texel[2 * channel],
+                        texel[2 * channel + 1],
+                    ]))
+                })
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// CPU side of the R32Float texture arrays, one layer per species
+#[derive(Clone, Debug)]
+pub struct TrailLayers {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<Vec<f32>>,
+}
+
+impl TrailLayers {
+    pub fn new(width: u32, height: u32, num_layers: usize) -> Self {
+        Self {
+            width,
+            height,
+            layers: vec![vec![0.0; (width * height) as usize]; num_layers],
+        }
+    }
+
+    /// Decodes the raw texels of a R32Float texture array, layer after layer
+    pub fn from_r32f(width: u32, height: u32, texels: &[u8]) -> Self {
+        let layers = texels
+            .chunks_exact(TEXEL_SIZE * (width * height) as usize)
+            .map(|layer| {
+                layer
+                    .chunks_exact(TEXEL_SIZE)
+                    .map(bytemuck::pod_read_unaligned)
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            layers,
+        }
+    }
+
+    /// Out of bounds loads return zero, as robust buffer access does
+    pub fn load(&self, layer: usize, coords: [i32; 2]) -> f32 {
+        match (self.layers.get(layer), self.index(coords)) {
+            (Some(layer), Some(index)) => layer[index],
+            _ => 0.0,
+        }
+    }
+
+    /// Out of bounds stores are dropped
+    pub fn store(&mut self, layer: usize, coords: [i32; 2], value: f32) {
+        if let (Some(index), Some(layer)) = (self.index(coords), self.layers.get_mut(layer)) {
+            layer[index] = value;
+        }
+    }
+
+    fn index(&self, [x, y]: [i32; 2]) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+
+        Some(y as usize * self.width as usize + x as usize)
+    }
+}
+
+/// Runs the same passes as `ComputeSlimePipeline` on the CPU, without a GPU or a window.
+/// Agents are updated one after the other, so same pixel deposits always resolve the same way
+pub struct CpuSimulation {
+    pub agents: Vec<Agent>,
+    pub species_settings: Vec<SpeciesSettings>,
+    pub species_interactions: Vec<f32>,
+    pub size_settings: SizeSettings,
+    pub slime_settings: SlimeSettings,
+    pub diffuse_settings: DiffuseSettings,
+    pub color_map_settings: ColorMapSettings,
+    pub species_colors: Vec<[f32; 4]>,
+
+    /// Trails kept from one step to the next, the display texture of the GPU path
+    pub display: TrailLayers,
+    trail: TrailLayers,
+    blur: TrailLayers,
+    diffuse: TrailLayers,
+}
+
+impl CpuSimulation {
+    pub fn new(app_settings: &AppSettings) -> Self {
+        let agents = spawn_agents(app_settings);
+
+        let size_settings = SizeSettings {
+            width: app_settings.width,
+            height: app_settings.height,
+        };
+        let trail_layers = || {
+            TrailLayers::new(
+                size_settings.width,
+                size_settings.height,
+                app_settings.num_species(),
+            )
+        };
+
+        let mut simulation = Self {
+            slime_settings: SlimeSettings {
+                num_agents: agents.len() as u32,
+                trail_weight: app_settings.trail_weight,
+                boundary_mode: app_settings.boundary_mode as u32,
+                num_species: app_settings.num_species() as u32,
+            },
+            agents,
+            species_settings: Vec::new(),
+            species_interactions: Vec::new(),
+            size_settings,
+            diffuse_settings: diffuse_settings(app_settings),
+            color_map_settings: color_map_settings(app_settings),
+            species_colors: Vec::new(),
+
+            display: trail_layers(),
+            trail: trail_layers(),
+            blur: trail_layers(),
+            diffuse: trail_layers(),
+        };
+
+        simulation.update_settings(app_settings);
+        simulation
+    }
+
+    /// Continues from a snapshot taken on the GPU path, its settings must already be applied
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.agents = snapshot.agents.clone();
+        self.slime_settings.num_agents = self.agents.len() as u32;
+        self.display = TrailLayers::from_r32f(
+            self.size_settings.width,
+            self.size_settings.height,
+            &snapshot.display,
+        );
+    }
+
+    /// Same settings as `ComputeSlimePipeline::update_settings`, applied without a reset
+    pub fn update_settings(&mut self, app_settings: &AppSettings) {
+        self.diffuse_settings = diffuse_settings(app_settings);
+
+        self.slime_settings = SlimeSettings {
+            num_agents: self.slime_settings.num_agents,
+            trail_weight: app_settings.trail_weight,
+            boundary_mode: app_settings.boundary_mode as u32,
+            num_species: app_settings.num_species() as u32,
+        };
+
+        self.species_settings = app_settings.species_settings.clone();
+        self.species_interactions = species_interactions(app_settings);
+        self.color_map_settings = color_map_settings(app_settings);
+        self.species_colors = species_colors(app_settings);
+    }
+
+    /// One simulation step: move and deposit onto a copy of the display, diffuse into the next
+    /// display. The GPU path gets the copy from the diffuse pass, which writes both textures
+    pub fn step(&mut self, time: &TimeBuffer) {
+        self.trail.layers.clone_from(&self.display.layers);
+
+        slime_step(
+            &mut self.agents,
+            &self.species_settings,
+            &self.species_interactions,
+            &self.size_settings,
+            &self.slime_settings,
+            time,
+            &self.display,
+            &mut self.trail,
+        );
+
+        diffuse_step(
+            &self.size_settings,
+            &self.diffuse_settings,
+            time,
+            &self.trail,
+            &mut self.blur,
+            &mut self.diffuse,
+        );
+
+        std::mem::swap(&mut self.display, &mut self.diffuse);
+    }
+
+    /// The coloured image the GPU path writes into the displayed texture
+    pub fn color_map(&self) -> TrailMap {
+        let mut output = TrailMap::new(self.size_settings.width, self.size_settings.height);
+        color_map(
+            &self.color_map_settings,
+            &self.species_colors,
+            &self.display,
+            &mut output,
+        );
+        output
+    }
+}
+
+/// `slime_main` of `slime_simulation.wgsl`
+#[allow(clippy::too_many_arguments)]
+pub fn slime_step(
+    agents: &mut [Agent],
+    species_settings: &[SpeciesSettings],
+    species_interactions: &[f32],
+    size_settings: &SizeSettings,
+    slime_settings: &SlimeSettings,
+    time: &TimeBuffer,
+    texture_read: &TrailLayers,
+    texture_write: &mut TrailLayers,
+) {
+    let total_agents = (slime_settings.num_agents as usize).min(agents.len());
+    let size = [size_settings.width as f32, size_settings.height as f32];
+
+    for (agent_index, agent_slot) in agents.iter_mut().take(total_agents).enumerate() {
+        let agent = *agent_slot;
+        // robust buffer access clamps out of range species
+        let species_setting =
+            &species_settings[(agent.species_index as usize).min(species_settings.len() - 1)];
+
+        let random = hash(
+            (agent.position[1] as u32)
+                .wrapping_mul(size_settings.width)
+                .wrapping_add(agent.position[0] as u32)
+                .wrapping_add(hash(
+                    (agent_index as u32)
+                        .wrapping_add(hash(time.frame.wrapping_add(hash(time.seed)))),
+                )),
+        );
+
+        let sense = |sensor_angle_offset: f32| {
+            sense(
+                &agent,
+                species_setting,
+                species_interactions,
+                size_settings,
+                slime_settings,
+                texture_read,
+                sensor_angle_offset,
+            )
+        };
+
+        let sensor_angle = species_setting.sensor_angle_spacing * (SHADER_PI / 180.0);
+        let weight_forward = sense(0.0);
+        let weight_left = sense(sensor_angle);
+        let weight_right = sense(-sensor_angle);
+
+        let random_steer_strength = scale_to_range(random);
+        let turn_speed = species_setting.turn_speed * SHADER_PI * 2.0;
+
+        let mut angle = agent.angle;
+        if weight_forward >= weight_left && weight_forward >= weight_right {
+            angle = agent.angle + 0.0;
+        } else if weight_forward < weight_left && weight_forward < weight_right {
+            angle =
+                agent.angle + (random_steer_strength - 0.5) * 2.0 * turn_speed * time.delta_time;
+        } else if weight_right > weight_left {
+            angle = agent.angle - random_steer_strength * turn_speed * time.delta_time;
+        } else if weight_left > weight_right {
+            angle = agent.angle + random_steer_strength * turn_speed * time.delta_time;
+        }
+
+        // the move uses the heading from before steering, as the shader does
+        let direction = [agent.angle.cos(), agent.angle.sin()];
+        let mut new_pos = [
+            agent.position[0] + direction[0] * species_setting.move_speed * time.delta_time,
+            agent.position[1] + direction[1] * species_setting.move_speed * time.delta_time,
+        ];
+
+        let mut deposit = true;
+
+        if new_pos[0] < 0.0 || new_pos[0] > size[0] || new_pos[1] < 0.0 || new_pos[1] > size[1] {
+            let new_rand = hash(random);
+            let random_angle = scale_to_range(new_rand) * SHADER_PI * 2.0;
+
+            match slime_settings.boundary_mode {
+                BOUNDARY_WRAP => {
+                    for axis in 0..2 {
+                        new_pos[axis] -= (new_pos[axis] / size[axis]).floor() * size[axis];
+                    }
+                }
+                BOUNDARY_REFLECT => {
+                    if new_pos[0] < 0.0 || new_pos[0] > size[0] {
+                        new_pos[0] = if new_pos[0] < 0.0 {
+                            -new_pos[0]
+                        } else {
+                            2.0 * size[0] - new_pos[0]
+                        };
+                        angle = SHADER_PI - angle;
+                    }
+                    if new_pos[1] < 0.0 || new_pos[1] > size[1] {
+                        new_pos[1] = if new_pos[1] < 0.0 {
+                            -new_pos[1]
+                        } else {
+                            2.0 * size[1] - new_pos[1]
+                        };
+                        angle = -angle;
+                    }
+                    new_pos = clamp_position(new_pos, size);
+                }
+                BOUNDARY_RESPAWN => {
+                    let rand_x = hash(new_rand);
+                    let rand_y = hash(rand_x);
+                    new_pos = [
+                        scale_to_range(rand_x) * (size[0] - 1.0),
+                        scale_to_range(rand_y) * (size[1] - 1.0),
+                    ];
+                    angle = random_angle;
+                }
+                _ => {
+                    new_pos = clamp_position(new_pos, size);
+                    angle = random_angle;
+                    deposit = false;
+                }
+            }
+        }
+
+        if deposit {
+            let current_pos = sample_coords(
+                [new_pos[0] as i32, new_pos[1] as i32],
+                size_settings,
+                slime_settings.boundary_mode,
+            );
+
+            // only the species layer is deposited, the other trails are kept
+            texture_write.store(
+                agent.species_index as usize,
+                current_pos,
+                slime_settings.trail_weight,
+            );
+        }
+
+        agent_slot.angle = angle;
+        agent_slot.position = new_pos;
+    }
+}
+
+/// `diffuse_main` of `diffuse.wgsl`
+pub fn diffuse_step(
+    size_settings: &SizeSettings,
+    diffuse_settings: &DiffuseSettings,
+    time: &TimeBuffer,
+    trail_texture: &TrailLayers,
+    blur_texture: &mut TrailLayers,
+    diffuse_texture: &mut TrailLayers,
+) {
+    let radius = diffuse_settings.kernel_radius as i32;
+    let size = 2 * radius + 1;
+    let kernel_weight =
+        |index: i32| diffuse_settings.kernel_weights[index as usize / 4][index as usize % 4];
+    let sample = |texture: &TrailLayers, layer: usize, coords: [i32; 2]| {
+        texture.load(
+            layer,
+            sample_coords(coords, size_settings, diffuse_settings.boundary_mode),
+        )
+    };
+
+    // every layer holds the trail of one species and is blurred on its own
+    for layer in 0..trail_texture.layers.len() {
+        // `diffuse_blur_main`
+        for y in 0..size_settings.height as i32 {
+            for x in 0..size_settings.width as i32 {
+                // the matrix rows, or the single row of a separable kernel
+                let rows = if diffuse_settings.kernel_matrix != 0 {
+                    -radius..=radius
+                } else {
+                    0..=0
+                };
+
+                let mut sum = 0.0;
+                for offset_y in rows {
+                    for offset_x in -radius..=radius {
+                        let weight = if diffuse_settings.kernel_matrix != 0 {
+                            kernel_weight((offset_y + radius) * size + offset_x + radius)
+                        } else {
+                            kernel_weight(offset_x + radius)
+                        };
+
+                        sum += weight * sample(trail_texture, layer, [x + offset_x, y + offset_y]);
+                    }
+                }
+
+                blur_texture.store(layer, [x, y], sum);
+            }
+        }
+
+        // `diffuse_main`
+        let diffuse_weight = (diffuse_settings.diffuse_rate * time.delta_time).clamp(0.0, 1.0);
+
+        for y in 0..size_settings.height as i32 {
+            for x in 0..size_settings.width as i32 {
+                let mut blurred_col = blur_texture.load(layer, [x, y]);
+                if diffuse_settings.kernel_matrix == 0 {
+                    blurred_col = 0.0;
+                    for offset_y in -radius..=radius {
+                        blurred_col += kernel_weight(offset_y + radius)
+                            * sample(blur_texture, layer, [x, y + offset_y]);
+                    }
+                }
+
+                let original_col = trail_texture.load(layer, [x, y]);
+                let blended_col =
+                    original_col * (1.0 - diffuse_weight) + blurred_col * diffuse_weight;
+                let output = (blended_col - diffuse_settings.decay_rate * time.delta_time).max(0.0);
+
+                diffuse_texture.store(layer, [x, y], output);
+            }
+        }
+    }
+}
+
+/// `color_map_main` of `color_map.wgsl`
+pub fn color_map(
+    color_map_settings: &ColorMapSettings,
+    species_colors: &[[f32; 4]],
+    texture_read: &TrailLayers,
+    texture_write: &mut TrailMap,
+) {
+    for (index, output) in texture_write.pixels.iter_mut().enumerate() {
+        // each layer holds the trail of a species, tinted with its display colour
+        let mut color = [0.0, 0.0, 0.0, 1.0];
+        let mut intensity = 0.0;
+        for (layer, species_color) in texture_read.layers.iter().zip(species_colors) {
+            for component in 0..3 {
+                color[component] += layer[index] * species_color[component];
+            }
+            intensity += layer[index];
+        }
+
+        if color_map_settings.use_gradient != 0 {
+            let gradient_color = sample_gradient(color_map_settings, intensity);
+
+            for component in 0..3 {
+                let tint = if intensity > 0.0 {
+                    color[component] / intensity
+                } else {
+                    1.0
+                };
+                color[component] = gradient_color[component] * tint;
+            }
+        }
+
+        *output = color.map(round_to_half);
+    }
+}
+
+/// Where a summed trail intensity falls on the gradient, in `0..=1`, after the intensity range
+/// and the gamma
+pub fn gradient_position(color_map_settings: &ColorMapSettings, intensity: f32) -> f32 {
+    let range = (color_map_settings.intensity_max - color_map_settings.intensity_min).max(0.000001);
+    let mapped = ((intensity - color_map_settings.intensity_min) / range).clamp(0.0, 1.0);
+    mapped.powf(color_map_settings.gamma)
+}
+
+/// `sample_gradient` of `color_map.wgsl`
+fn sample_gradient(color_map_settings: &ColorMapSettings, intensity: f32) -> [f32; 3] {
+    let gradient = &color_map_settings.gradient;
+    let position = gradient_position(color_map_settings, intensity) * (GRADIENT_SIZE - 1) as f32;
+    let index = position.floor() as usize;
+    let next_index = (index + 1).min(GRADIENT_SIZE - 1);
+    let amount = position - position.floor();
+
+    [0, 1, 2].map(|component| {
+        gradient[index][component]
+            + (gradient[next_index][component] - gradient[index][component]) * amount
+    })
+}
+
+fn sense(
+    agent: &Agent,
+    species_setting: &SpeciesSettings,
+    species_interactions: &[f32],
+    size_settings: &SizeSettings,
+    slime_settings: &SlimeSettings,
+    texture_read: &TrailLayers,
+    sensor_angle_offset: f32,
+) -> f32 {
+    let sensor_angle = agent.angle + sensor_angle_offset;
+    let sensor_dir = [sensor_angle.cos(), sensor_angle.sin()];
+
+    let sensor_pos_x =
+        (agent.position[0] + sensor_dir[0] * species_setting.sensor_offset_dst) as i32;
+    let sensor_pos_y =
+        (agent.position[1] + sensor_dir[1] * species_setting.sensor_offset_dst) as i32;
+
+    // every species has its own trail layer
+    let num_species = slime_settings.num_species as usize;
+    let weights_offset = agent.species_index as usize * num_species;
+    let sensor_size = species_setting.sensor_size as i32;
+
+    let mut sum = 0.0;
+    for offset_x in -sensor_size..=sensor_size {
+        for offset_y in -sensor_size..=sensor_size {
+            let sample_pos = sample_coords(
+                [sensor_pos_x + offset_x, sensor_pos_y + offset_y],
+                size_settings,
+                slime_settings.boundary_mode,
+            );
+
+            for species in 0..num_species {
+                let weight = species_interactions
+                    .get(weights_offset + species)
+                    .copied()
+                    .unwrap_or(0.0);
+                sum += weight * texture_read.load(species, sample_pos);
+            }
+        }
+    }
+
+    sum
+}
+
+fn sample_coords(coords: [i32; 2], size_settings: &SizeSettings, boundary_mode: u32) -> [i32; 2] {
+    let size = [size_settings.width as i32, size_settings.height as i32];
+
+    if boundary_mode == BOUNDARY_WRAP {
+        return [
+            ((coords[0] % size[0]) + size[0]) % size[0],
+            ((coords[1] % size[1]) + size[1]) % size[1],
+        ];
+    }
+
+    [
+        coords[0].max(0).min(size[0] - 1),
+        coords[1].max(0).min(size[1] - 1),
+    ]
+}
+
+fn clamp_position(position: [f32; 2], size: [f32; 2]) -> [f32; 2] {
+    [
+        position[0].max(0.0).min(size[0] - 1.0),
+        position[1].max(0.0).min(size[1] - 1.0),
+    ]
+}
+
+fn hash(value: u32) -> u32 {
+    let mut state = value;
+    state ^= 2747636419;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state = state.wrapping_mul(2654435769);
+    state
+}
+
+fn scale_to_range(state: u32) -> f32 {
+    state as f32 / 4294967295.0
+}
+
+/// Decodes the bits of a 16 bits float
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Rounds to the nearest value a 16 bits float holds, ties to even
+fn round_to_half(value: f32) -> f32 {
+    if !value.is_finite() || value == 0.0 {
+        return value;
+    }
+
+    if value.abs() >= 65520.0 {
+        return f32::INFINITY.copysign(value);
+    }
+
+    // half floats keep 10 mantissa bits, below the smallest normal the spacing stays 2^-24
+    let exponent = (((value.to_bits() >> 23) & 0xff) as i32 - 127).max(-14);
+    let spacing = 2f32.powi(exponent - 10);
+
+    round_ties_even(value / spacing) * spacing
+}
+
+/// `f32::round` breaks ties away from zero, half of a tie is always exact
+fn round_ties_even(value: f32) -> f32 {
+    if (value - value.trunc()).abs() == 0.5 {
+        2.0 * (value / 2.0).round()
+    } else {
+        value.round()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_to_f32_decodes_known_values() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x2e66), 0.099975586);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn round_to_half_matches_half_precision() {
+        assert_eq!(round_to_half(0.1), half_to_f32(0x2e66));
+        assert_eq!(round_to_half(65504.0), 65504.0);
+        assert_eq!(round_to_half(65519.0), 65504.0);
+        assert_eq!(round_to_half(65520.0), f32::INFINITY);
+        assert_eq!(round_to_half(-70000.0), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn round_to_half_breaks_ties_to_even() {
+        let ulp = 2f32.powi(-10);
+        // halfway between 1 and the next half, the even mantissa is 1
+        assert_eq!(round_to_half(1.0 + ulp / 2.0), 1.0);
+        // halfway between an odd and an even mantissa
+        assert_eq!(round_to_half(1.0 + 3.0 * ulp / 2.0), 1.0 + 2.0 * ulp);
+        assert_eq!(round_to_half(-1.0 - 3.0 * ulp / 2.0), -1.0 - 2.0 * ulp);
+
+        // subnormals are spaced by 2^-24
+        let subnormal = 2f32.powi(-24);
+        assert_eq!(round_to_half(subnormal / 2.0), 0.0);
+        assert_eq!(round_to_half(3.0 * subnormal / 2.0), 2.0 * subnormal);
+    }
+
+    #[test]
+    fn half_values_round_trip() {
+        for bits in 0..=u16::MAX {
+            let value = half_to_f32(bits);
+            if value.is_nan() {
+                continue;
+            }
+
+            assert_eq!(
+                round_to_half(value).to_bits(),
+                value.to_bits(),
+                "{:#06x}",
+                bits
+            );
+        }
+    }
+
+    fn small_settings(seed: u64) -> AppSettings {
+        AppSettings {
+            width: 32,
+            height: 24,
+            num_agents: 300,
+            seed,
+            ..AppSettings::default()
+        }
+    }
+
+    fn run(app_settings: &AppSettings, steps: u32) -> CpuSimulation {
+        let mut simulation = CpuSimulation::new(app_settings);
+        for frame in 0..steps {
+            simulation.step(&TimeBuffer {
+                time: frame as f32 * app_settings.time_step,
+                delta_time: app_settings.time_step,
+                frame,
+                seed: crate::compute_slime_pipeline::shader_seed(app_settings.seed),
+            });
+        }
+        simulation
+    }
+
+    fn agent_bits(simulation: &CpuSimulation) -> Vec<[u32; 4]> {
+        simulation
+            .agents
+            .iter()
+            .map(|agent| {
+                [
+                    agent.position[0].to_bits(),
+                    agent.position[1].to_bits(),
+                    agent.angle.to_bits(),
+                    agent.species_index,
+                ]
+            })
+            .collect()
+    }
+
+    fn pixel_bits(trail_layers: &TrailLayers) -> Vec<u32> {
+        trail_layers
+            .layers
+            .iter()
+            .flatten()
+            .map(|value| value.to_bits())
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_output() {
+        let first = run(&small_settings(7), 20);
+        let second = run(&small_settings(7), 20);
+        assert_eq!(agent_bits(&first), agent_bits(&second));
+        assert_eq!(pixel_bits(&first.display), pixel_bits(&second.display));
+
+        let other = run(&small_settings(8), 20);
+        assert_ne!(agent_bits(&first), agent_bits(&other));
+    }
+
+    /// Moves a single agent of the last species one pixel past the right edge of a 16x8 map
+    fn step_past_edge_with(boundary_mode: BoundaryMode, num_species: u32) -> (Agent, TrailLayers) {
+        let size_settings = SizeSettings {
+            width: 16,
+            height: 8,
+        };
+        let slime_settings = SlimeSettings {
+            num_agents: 1,
+            trail_weight: 1.0,
+            boundary_mode: boundary_mode as u32,
+            num_species,
+        };
+        let species_settings = vec![
+            SpeciesSettings {
+                move_speed: 2.0,
+                ..SpeciesSettings::default()
+            };
+            num_species as usize
+        ];
+        let species_interactions = vec![1.0; (num_species * num_species) as usize];
+        let time = TimeBuffer {
+            time: 0.0,
+            delta_time: 0.5,
+            frame: 0,
+            seed: 0,
+        };
+
+        let mut agents = [Agent {
+            position: [15.5, 4.0],
+            angle: 0.0,
+            species_index: num_species - 1,
+        }];
+        let texture_read = TrailLayers::new(16, 8, num_species as usize);
+        let mut texture_write = TrailLayers::new(16, 8, num_species as usize);
+
+        slime_step(
+            &mut agents,
+            &species_settings,
+            &species_interactions,
+            &size_settings,
+            &slime_settings,
+            &time,
+            &texture_read,
+            &mut texture_write,
+        );
+
+        (agents[0], texture_write)
+    }
+
+    fn step_past_edge(boundary_mode: BoundaryMode) -> (Agent, TrailLayers) {
+        step_past_edge_with(boundary_mode, 1)
+    }
+
+    fn layer_deposits(trail_layers: &TrailLayers, layer: usize) -> Vec<[i32; 2]> {
+        (0..trail_layers.height as i32)
+            .flat_map(|y| (0..trail_layers.width as i32).map(move |x| [x, y]))
+            .filter(|&coords| trail_layers.load(layer, coords) > 0.0)
+            .collect()
+    }
+
+    fn deposits(trail_layers: &TrailLayers) -> Vec<[i32; 2]> {
+        layer_deposits(trail_layers, 0)
+    }
+
+    #[test]
+    fn wrap_moves_agents_to_the_opposite_edge() {
+        let (agent, trail) = step_past_edge(BoundaryMode::Wrap);
+        assert_eq!(agent.position, [0.5, 4.0]);
+        assert_eq!(agent.angle, 0.0);
+        assert_eq!(deposits(&trail), vec![[0, 4]]);
+    }
+
+    #[test]
+    fn reflect_mirrors_position_and_heading() {
+        let (agent, trail) = step_past_edge(BoundaryMode::Reflect);
+        assert_eq!(agent.position, [15.0, 4.0]);
+        assert_eq!(agent.angle, SHADER_PI);
+        assert_eq!(deposits(&trail), vec![[15, 4]]);
+    }
+
+    #[test]
+    fn clamp_stops_at_the_edge_without_depositing() {
+        let (agent, trail) = step_past_edge(BoundaryMode::Clamp);
+        assert_eq!(agent.position, [15.0, 4.0]);
+        assert!((0.0..=2.0 * SHADER_PI).contains(&agent.angle));
+        assert!(deposits(&trail).is_empty());
+    }
+
+    #[test]
+    fn respawn_moves_agents_inside_the_map() {
+        let (agent, trail) = step_past_edge(BoundaryMode::Respawn);
+        assert!((0.0..=15.0).contains(&agent.position[0]));
+        assert!((0.0..=7.0).contains(&agent.position[1]));
+        assert_eq!(
+            deposits(&trail),
+            vec![[agent.position[0] as i32, agent.position[1] as i32]]
+        );
+    }
+
+    #[test]
+    fn species_deposit_into_their_own_layer() {
+        let (_, trail) = step_past_edge_with(BoundaryMode::Wrap, 6);
+        assert_eq!(layer_deposits(&trail, 5), vec![[0, 4]]);
+        for layer in 0..5 {
+            assert!(layer_deposits(&trail, layer).is_empty(), "{}", layer);
+        }
+    }
+
+    fn diffuse(trail: &TrailLayers, decay_rate: f32) -> TrailLayers {
+        let app_settings = AppSettings {
+            boundary_mode: BoundaryMode::Wrap,
+            diffusion_kernel: crate::types::DiffusionKernel::Box { radius: 1 },
+            decay_rate,
+            diffuse_rate: 1.0,
+            ..AppSettings::default()
+        };
+        let size_settings = SizeSettings {
+            width: trail.width,
+            height: trail.height,
+        };
+        let time = TimeBuffer {
+            time: 0.0,
+            delta_time: 0.5,
+            frame: 0,
+            seed: 0,
+        };
+
+        let mut blur = TrailLayers::new(trail.width, trail.height, trail.layers.len());
+        let mut output = TrailLayers::new(trail.width, trail.height, trail.layers.len());
+        diffuse_step(
+            &size_settings,
+            &diffuse_settings(&app_settings),
+            &time,
+            trail,
+            &mut blur,
+            &mut output,
+        );
+        output
+    }
+
+    fn layer_sum(trail_layers: &TrailLayers, layer: usize) -> f32 {
+        trail_layers.layers[layer].iter().sum()
+    }
+
+    #[test]
+    fn box_diffusion_keeps_the_trails_and_removes_the_decay() {
+        let mut trail = TrailLayers::new(8, 6, 2);
+        for (index, value) in trail.layers[0].iter_mut().enumerate() {
+            *value = 0.5 + (index % 5) as f32 * 0.125;
+        }
+
+        // a wrapped box blur moves the trails around without changing their sum, the decay then
+        // takes `decay_rate * delta_time` from every pixel
+        let output = diffuse(&trail, 0.25);
+        let expected = layer_sum(&trail, 0) - 0.125 * trail.layers[0].len() as f32;
+        assert!((layer_sum(&output, 0) - expected).abs() < 0.05);
+        assert_eq!(layer_sum(&output, 1), 0.0);
+    }
+
+    #[test]
+    fn decay_never_makes_trails_negative() {
+        let mut trail = TrailLayers::new(8, 6, 2);
+        trail.store(0, [3, 2], 1.0);
+        trail.store(1, [3, 2], 0.5);
+
+        let output = diffuse(&trail, 0.25);
+        assert!(output.layers.iter().flatten().all(|&value| value >= 0.0));
+
+        // half of the pixel is blended with its blur, the ninth reaching each neighbour is less
+        // than the decay
+        assert_eq!(deposits(&output), vec![[3, 2]]);
+        let expected = 0.5 + 0.5 / 9.0 - 0.125;
+        assert!((output.load(0, [3, 2]) - expected).abs() < 0.001);
+    }
+
+    #[test]
+    fn color_map_blends_the_species_colours() {
+        let mut app_settings = AppSettings::default();
+        app_settings.add_species();
+        app_settings.add_species();
+        let species_colors = species_colors(&app_settings);
+
+        let mut trail = TrailLayers::new(2, 1, 6);
+        trail.store(4, [0, 0], 0.5);
+        trail.store(5, [0, 0], 0.25);
+
+        let mut output = TrailMap::new(2, 1);
+        color_map(
+            &color_map_settings(&app_settings),
+            &species_colors,
+            &trail,
+            &mut output,
+        );
+
+        let expected = [0, 1, 2].map(|component| {
+            0.5 * species_colors[4][component] + 0.25 * species_colors[5][component]
+        });
+        for (value, expected) in output.pixels[0].iter().zip(expected) {
+            assert!((value - expected).abs() < 0.001);
+        }
+        assert_eq!(output.pixels[1], [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn gradient_position_follows_range_and_gamma() {
+        let color_map_settings = color_map_settings(&AppSettings {
+            palette_range: [0.5, 1.5],
+            palette_gamma: 2.0,
+            ..AppSettings::default()
+        });
+        assert_eq!(gradient_position(&color_map_settings, 0.2), 0.0);
+        assert_eq!(gradient_position(&color_map_settings, 1.0), 0.25);
+        assert_eq!(gradient_position(&color_map_settings, 3.0), 1.0);
+    }
+}
@@ -1,28 +1,91 @@
-use bevy::prelude::{App, Input, KeyCode, Plugin, Res, ResMut};
+use std::{path::PathBuf, sync::Arc};
+
+use bevy::{
+    prelude::{App, Input, KeyCode, MouseButton, Plugin, Res, ResMut},
+    window::Windows,
+};
 use bevy_egui::{
-    egui::{ComboBox, DragValue, Ui, Window},
+    egui::{
+        pos2, vec2, Button, CollapsingHeader, Color32, ComboBox, DragValue, Grid, Rect, Response,
+        ScrollArea, Sense, Stroke, TopBottomPanel, Ui, Window,
+    },
     EguiContext, EguiPlugin,
 };
 
 use crate::{
-    types::{AgentDistribution, AppPreset, AppSettings, SpeciesSettings},
-    AppSettingsUpdated, AppShouldReset,
+    compute_slime_pipeline::{color_map_settings, MAX_AGENTS, MAX_SUBSTEPS},
+    cpu_simulation::gradient_position,
+    image_export::ImageFormat,
+    presets::{list_user_presets, user_preset_path},
+    recording::{RecordingFormat, RecordingSettings},
+    screenshot::ScreenshotRequest,
+    settings_watcher::SettingsWatcher,
+    snapshot::Snapshot,
+    types::{
+        AgentDistribution, AppPreset, AppSettings, BoundaryMode, DiffusionKernel, Gradient,
+        Palette, SpeciesAssignment, SpeciesSettings, MAX_KERNEL_RADIUS, MAX_MATRIX_RADIUS,
+    },
+    AgentSpawnCursor, AppPaused, AppPipelineError, AppRecording, AppRestoreSnapshot,
+    AppSaveScreenshot, AppSaveSnapshot, AppSettingsUpdated, AppShouldReset, AppStepOnce,
 };
 
-pub struct GuiPlugin;
+/// Smallest gap kept between the ends of the palette intensity range
+const MIN_PALETTE_SPAN: f32 = 0.01;
+
+pub struct GuiPlugin {
+    /// Opens the settings window at start
+    pub show_window: bool,
+}
 
 impl Plugin for GuiPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(EguiPlugin)
-            .init_resource::<WindowState>()
+            .insert_resource(WindowState {
+                is_window_open: self.show_window,
+                ..Default::default()
+            })
             .add_system(update_window_open)
-            .add_system(egui_system);
+            .add_system(take_screenshot_on_key)
+            .add_system(toggle_recording_on_key)
+            .add_system(egui_system)
+            .add_system(pipeline_error_system)
+            .add_system(spawn_agents_at_cursor);
     }
 }
 
-#[derive(Default)]
 struct WindowState {
     is_window_open: bool,
+    spawn_at_cursor: bool,
+    agents_per_click: u32,
+    /// File name, without extension, the next preset is saved under
+    preset_name: String,
+    preset_error: Option<String>,
+    snapshot_path: String,
+    snapshot_error: Option<String>,
+    screenshot_format: ImageFormat,
+    recording_format: RecordingFormat,
+    /// Rendered frames between two recorded frames
+    recording_every: u32,
+    /// Simulation size being edited, applied once the drag ends
+    resolution: [u32; 2],
+}
+
+impl Default for WindowState {
+    fn default() -> Self {
+        Self {
+            is_window_open: false,
+            spawn_at_cursor: false,
+            agents_per_click: 1000,
+            preset_name: String::new(),
+            preset_error: None,
+            snapshot_path: String::from("snapshot.bin"),
+            snapshot_error: None,
+            screenshot_format: ImageFormat::Png8,
+            recording_format: RecordingFormat::PngSequence,
+            recording_every: 1,
+            resolution: [0, 0],
+        }
+    }
 }
 
 fn update_window_open(keyboard_input: Res<Input<KeyCode>>, mut window_state: ResMut<WindowState>) {
@@ -31,22 +94,125 @@ fn update_window_open(keyboard_input: Res<Input<KeyCode>>, mut window_state: Res
     }
 }
 
+fn take_screenshot_on_key(
+    keyboard_input: Res<Input<KeyCode>>,
+    window_state: Res<WindowState>,
+    mut app_save_screenshot: ResMut<AppSaveScreenshot>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        app_save_screenshot.0 = Some(ScreenshotRequest::timestamped(
+            window_state.screenshot_format,
+        ));
+    }
+}
+
+fn toggle_recording_on_key(
+    keyboard_input: Res<Input<KeyCode>>,
+    window_state: Res<WindowState>,
+    mut app_recording: ResMut<AppRecording>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        toggle_recording(
+            window_state.recording_format,
+            window_state.recording_every,
+            &mut app_recording,
+        );
+    }
+}
+
+fn toggle_recording(format: RecordingFormat, every: u32, app_recording: &mut AppRecording) {
+    app_recording.0 = match app_recording.0 {
+        Some(_) => None,
+        None => Some(RecordingSettings::timestamped(format, every)),
+    };
+}
+
+/// Adds agents around the clicked point of the simulation, clicks on the GUI are ignored
+fn spawn_agents_at_cursor(
+    window_state: Res<WindowState>,
+    mut egui_context: ResMut<EguiContext>,
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    mut app_settings: ResMut<AppSettings>,
+    mut agent_spawn_cursor: ResMut<AgentSpawnCursor>,
+) {
+    agent_spawn_cursor.0 = None;
+
+    if !window_state.spawn_at_cursor
+        || !mouse_input.just_pressed(MouseButton::Left)
+        || egui_context.ctx_mut().wants_pointer_input()
+    {
+        return;
+    }
+
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let cursor = match window.cursor_position() {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    // the display sprite is centered in the window, texture rows go downwards
+    let x = app_settings.width as f32 / 2.0 + cursor.x - window.width() / 2.0;
+    let y = app_settings.height as f32 / 2.0 - cursor.y + window.height() / 2.0;
+
+    if x < 0.0 || y < 0.0 || x >= app_settings.width as f32 || y >= app_settings.height as f32 {
+        return;
+    }
+
+    app_settings.num_agents = app_settings
+        .num_agents
+        .saturating_add(window_state.agents_per_click)
+        .min(MAX_AGENTS);
+    agent_spawn_cursor.0 = Some([x, y]);
+}
+
+/// Shows why the simulation does not run, until the failed shader compiles
+fn pipeline_error_system(
+    mut egui_context: ResMut<EguiContext>,
+    app_pipeline_error: Res<AppPipelineError>,
+) {
+    let error = app_pipeline_error.0.lock().unwrap().clone();
+
+    if let Some(error) = error {
+        TopBottomPanel::bottom("pipeline_error").show(egui_context.ctx_mut(), |ui| {
+            ui.colored_label(Color32::RED, error.summary());
+            ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                ui.monospace(&error.message);
+            });
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn egui_system(
+    settings_watcher: Res<SettingsWatcher>,
     mut window_state: ResMut<WindowState>,
     mut egui_context: ResMut<EguiContext>,
     mut app_settings: ResMut<AppSettings>,
     mut app_should_reset: ResMut<AppShouldReset>,
     mut app_settings_updated: ResMut<AppSettingsUpdated>,
+    mut app_paused: ResMut<AppPaused>,
+    mut app_step_once: ResMut<AppStepOnce>,
+    mut app_save_snapshot: ResMut<AppSaveSnapshot>,
+    mut app_restore_snapshot: ResMut<AppRestoreSnapshot>,
+    mut app_save_screenshot: ResMut<AppSaveScreenshot>,
+    mut app_recording: ResMut<AppRecording>,
 ) {
+    let window_state = &mut *window_state;
     Window::new("Slime Simulation")
         .resizable(false)
         .open(&mut window_state.is_window_open)
         .show(egui_context.ctx_mut(), |ui| {
             let mut reset_simulation = false;
             let mut settings_updated = false;
+            let mut step_once = false;
 
             ComboBox::from_label("Simulation presets")
-                .selected_text(format!("{:?}", app_settings.app_preset))
+                .selected_text(app_settings.app_preset.name())
                 .show_ui(ui, |ui| {
                     ui.selectable_value(
                         &mut app_settings.app_preset,
@@ -58,11 +224,50 @@ fn egui_system(
                         AppPreset::SuperNova,
                         "SuperNova",
                     );
+
+                    // the directory is only scanned while the list is open
+                    for path in list_user_presets() {
+                        let preset = AppPreset::File(path);
+                        let name = preset.name();
+                        ui.selectable_value(&mut app_settings.app_preset, preset, name);
+                    }
                 });
 
             if ui.button("Apply preset to Simulation and Reset").clicked() {
-                app_settings.apply_preset();
-                reset_simulation = true;
+                match app_settings.apply_preset() {
+                    Ok(()) => {
+                        window_state.preset_error = None;
+                        reset_simulation = true;
+                    }
+                    Err(error) => window_state.preset_error = Some(error),
+                }
+            }
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut window_state.preset_name);
+
+                let preset_name = window_state.preset_name.trim();
+                if ui
+                    .add_enabled(!preset_name.is_empty(), Button::new("Save preset"))
+                    .clicked()
+                {
+                    let saved = user_preset_path(preset_name)
+                        .and_then(|path| app_settings.save(&path).map(|()| path));
+                    match saved {
+                        Ok(path) => {
+                            window_state.preset_error = None;
+                            app_settings.app_preset = AppPreset::File(path);
+                        }
+                        Err(error) => window_state.preset_error = Some(error),
+                    }
+                }
+            });
+
+            if let Some(error) = &window_state.preset_error {
+                ui.colored_label(Color32::RED, error);
+            }
+            if let Some(error) = &settings_watcher.error {
+                ui.colored_label(Color32::RED, format!("Preset reload failed: {}", error));
             }
 
             ui.separator();
@@ -92,15 +297,119 @@ fn egui_system(
                     );
                 });
 
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(&mut app_settings.seed).speed(1));
+                ui.label("Seed (applied on reset)");
+
+                if ui.button("New seed").clicked() {
+                    app_settings.seed = rand::random();
+                }
+            });
+
             if ui.button("Reset Simulation").clicked() {
                 reset_simulation = true;
             }
 
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut window_state.snapshot_path);
+
+                let snapshot_path = PathBuf::from(window_state.snapshot_path.trim());
+
+                // written by the render world, failures are logged
+                if ui.button("Save snapshot").clicked() {
+                    app_save_snapshot.0 = Some(snapshot_path.clone());
+                }
+
+                if ui.button("Load snapshot and Reset").clicked() {
+                    match Snapshot::load(&snapshot_path) {
+                        Ok(snapshot) => {
+                            window_state.snapshot_error = None;
+                            *app_settings = snapshot.app_settings.clone();
+                            app_restore_snapshot.0 = Some(Arc::new(snapshot));
+                            reset_simulation = true;
+                        }
+                        Err(error) => window_state.snapshot_error = Some(error),
+                    }
+                }
+            });
+
+            if let Some(error) = &window_state.snapshot_error {
+                ui.colored_label(Color32::RED, error);
+            }
+
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source("screenshot_format")
+                    .selected_text(image_format_name(window_state.screenshot_format))
+                    .show_ui(ui, |ui| {
+                        for format in [ImageFormat::Png8, ImageFormat::Png16, ImageFormat::Exr] {
+                            ui.selectable_value(
+                                &mut window_state.screenshot_format,
+                                format,
+                                image_format_name(format),
+                            );
+                        }
+                    });
+
+                if ui.button("Screenshot (F12)").clicked() {
+                    app_save_screenshot.0 = Some(ScreenshotRequest::timestamped(
+                        window_state.screenshot_format,
+                    ));
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let recording = app_recording.0.is_some();
+
+                // the format and interval of a running recording are fixed until it stops
+                ui.add_enabled_ui(!recording, |ui| {
+                    ComboBox::from_id_source("recording_format")
+                        .selected_text(recording_format_name(window_state.recording_format))
+                        .show_ui(ui, |ui| {
+                            for format in [RecordingFormat::PngSequence, RecordingFormat::Y4m] {
+                                ui.selectable_value(
+                                    &mut window_state.recording_format,
+                                    format,
+                                    recording_format_name(format),
+                                );
+                            }
+                        });
+
+                    ui.add(
+                        DragValue::new(&mut window_state.recording_every)
+                            .prefix("every ")
+                            .suffix(" frames")
+                            .clamp_range(1..=1000),
+                    );
+                });
+
+                let recording_label = if recording {
+                    "Stop recording (F9)"
+                } else {
+                    "Record (F9)"
+                };
+                if ui.button(recording_label).clicked() {
+                    toggle_recording(
+                        window_state.recording_format,
+                        window_state.recording_every,
+                        &mut app_recording,
+                    );
+                }
+            });
+
+            if let Some(recording) = &app_recording.0 {
+                ui.label(format!("Recording to {}", recording.path.display()));
+            }
+
             ui.separator();
 
             if ui.button("Reload settings").clicked() {
-                app_settings.reset_settings();
-                settings_updated = true;
+                match app_settings.reset_settings() {
+                    Ok(()) => {
+                        window_state.preset_error = None;
+                        settings_updated = true;
+                    }
+                    Err(error) => window_state.preset_error = Some(error),
+                }
             };
 
             settings_updated = settings_updated
@@ -108,6 +417,65 @@ fn egui_system(
                     .checkbox(&mut app_settings.render_sensors, "render agent sense")
                     .changed();
 
+            let previous_boundary_mode = app_settings.boundary_mode;
+            ComboBox::from_label("World boundary")
+                .selected_text(format!("{:?}", app_settings.boundary_mode))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut app_settings.boundary_mode,
+                        BoundaryMode::Wrap,
+                        "Wrap",
+                    );
+                    ui.selectable_value(
+                        &mut app_settings.boundary_mode,
+                        BoundaryMode::Reflect,
+                        "Reflect",
+                    );
+                    ui.selectable_value(
+                        &mut app_settings.boundary_mode,
+                        BoundaryMode::Clamp,
+                        "Clamp",
+                    );
+                    ui.selectable_value(
+                        &mut app_settings.boundary_mode,
+                        BoundaryMode::Respawn,
+                        "Respawn",
+                    );
+                });
+            settings_updated =
+                settings_updated || previous_boundary_mode != app_settings.boundary_mode;
+
+            ComboBox::from_label("Trail palette")
+                .selected_text(app_settings.palette.name())
+                .show_ui(ui, |ui| {
+                    for palette in [
+                        Palette::Species,
+                        Palette::Viridis,
+                        Palette::Magma,
+                        Palette::Fire,
+                    ] {
+                        let name = palette.name();
+                        let selected = ui
+                            .selectable_value(&mut app_settings.palette, palette, name)
+                            .changed();
+                        settings_updated = settings_updated || selected;
+                    }
+
+                    // a custom gradient starts from the palette it replaces
+                    let is_custom = matches!(app_settings.palette, Palette::Custom(_));
+                    if ui.selectable_label(is_custom, "Custom").clicked() && !is_custom {
+                        let gradient = app_settings
+                            .palette
+                            .gradient()
+                            .unwrap_or_else(|| Palette::Viridis.gradient().unwrap());
+                        app_settings.palette = Palette::Custom(gradient);
+                        settings_updated = true;
+                    }
+                });
+
+            let palette_updated = add_palette_editor(&mut app_settings, ui);
+            settings_updated = settings_updated || palette_updated;
+
             ui.horizontal(|ui| {
                 settings_updated = settings_updated
                     || ui
@@ -124,47 +492,510 @@ fn egui_system(
                 ui.label("Diffuse rate");
             });
 
+            let kernel_updated =
+                add_diffusion_kernel_settings(&mut app_settings.diffusion_kernel, ui);
+            settings_updated = settings_updated || kernel_updated;
+
             ui.separator();
 
-            ui.heading("Species settings");
+            ui.horizontal(|ui| {
+                let pause_label = if app_paused.0 { "Resume" } else { "Pause" };
+                if ui.button(pause_label).clicked() {
+                    app_paused.0 = !app_paused.0;
+                }
 
-            add_species_settings(
-                &mut app_settings.species_settings[0],
-                &mut settings_updated,
-                ui,
-                0,
-            );
-            add_species_settings(
-                &mut app_settings.species_settings[1],
-                &mut settings_updated,
-                ui,
-                1,
+                step_once = ui.add_enabled(app_paused.0, Button::new("Step")).clicked();
+            });
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    DragValue::new(&mut app_settings.time_step)
+                        .speed(0.001)
+                        .clamp_range(0.0001..=1.0),
+                );
+                ui.label("Time step (s)");
+            });
+
+            ui.horizontal(|ui| {
+                ui.add(DragValue::new(&mut app_settings.substeps).clamp_range(1..=MAX_SUBSTEPS));
+                ui.label("Steps per frame");
+            });
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    DragValue::new(&mut app_settings.time_scale)
+                        .speed(0.01)
+                        .clamp_range(0.0..=10.0),
+                );
+                ui.label("Time scale (steps per frame multiplier)");
+            });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    DragValue::new(&mut app_settings.num_agents)
+                        .speed(100)
+                        .clamp_range(1..=MAX_AGENTS),
+                );
+                ui.label("Agents (new ones use the distribution)");
+            });
+
+            ui.checkbox(
+                &mut window_state.spawn_at_cursor,
+                "Click on the simulation to spawn agents",
             );
-            add_species_settings(
-                &mut app_settings.species_settings[2],
-                &mut settings_updated,
-                ui,
-                2,
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    DragValue::new(&mut window_state.agents_per_click)
+                        .speed(10)
+                        .clamp_range(1..=MAX_AGENTS),
+                );
+                ui.label("Agents per click");
+            });
+
+            ui.separator();
+
+            ui.checkbox(&mut app_settings.fit_to_window, "Fit simulation to window");
+
+            ui.add_enabled_ui(!app_settings.fit_to_window, |ui| {
+                ui.horizontal(|ui| {
+                    let resolution = &mut window_state.resolution;
+                    let width = ui.add(
+                        DragValue::new(&mut resolution[0])
+                            .speed(1)
+                            .clamp_range(16..=8192),
+                    );
+                    ui.label("x");
+                    let height = ui.add(
+                        DragValue::new(&mut resolution[1])
+                            .speed(1)
+                            .clamp_range(16..=8192),
+                    );
+                    ui.label("Resolution");
+
+                    // every new size resamples the trails, only the one the drag ends on is used
+                    let applied =
+                        |response: &Response| response.drag_released() || response.lost_focus();
+                    let editing = |response: &Response| response.dragged() || response.has_focus();
+                    if applied(&width) || applied(&height) {
+                        app_settings.width = resolution[0];
+                        app_settings.height = resolution[1];
+                    } else if !editing(&width) && !editing(&height) {
+                        *resolution = [app_settings.width, app_settings.height];
+                    }
+                });
+            });
+
+            ui.checkbox(
+                &mut app_settings.resample_on_resize,
+                "Keep trails on resize",
             );
-            add_species_settings(
-                &mut app_settings.species_settings[3],
-                &mut settings_updated,
-                ui,
-                3,
+            ui.checkbox(
+                &mut app_settings.scale_agents_on_resize,
+                "Scale agent positions on resize",
             );
 
-            app_should_reset.0 = reset_simulation;
-            app_settings_updated.0 = settings_updated;
+            ui.separator();
+
+            ui.heading("Species settings");
+
+            ComboBox::from_label("Species assignment at creation")
+                .selected_text(format!("{:?}", app_settings.species_assignment))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut app_settings.species_assignment,
+                        SpeciesAssignment::Interleaved,
+                        "Interleaved",
+                    );
+                    ui.selectable_value(
+                        &mut app_settings.species_assignment,
+                        SpeciesAssignment::Sectored,
+                        "Sectored",
+                    );
+                    ui.selectable_value(
+                        &mut app_settings.species_assignment,
+                        SpeciesAssignment::Random,
+                        "Random",
+                    );
+                });
+
+            let total_population: f32 = app_settings
+                .species_population
+                .iter()
+                .map(|share| share.max(0.0))
+                .sum();
+
+            let num_species = app_settings.num_species();
+            let mut removed_species = None;
+
+            let app_settings = &mut *app_settings;
+            ScrollArea::vertical()
+                .id_source("species_settings")
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for (species_index, ((species_settings, population), color)) in app_settings
+                        .species_settings
+                        .iter_mut()
+                        .zip(app_settings.species_population.iter_mut())
+                        .zip(app_settings.species_colors.iter_mut())
+                        .enumerate()
+                    {
+                        let remove = add_species_settings(
+                            species_settings,
+                            population,
+                            color,
+                            total_population,
+                            num_species > 1,
+                            &mut settings_updated,
+                            ui,
+                            species_index,
+                        );
+
+                        if remove {
+                            removed_species = Some(species_index);
+                        }
+                    }
+                });
+
+            if let Some(species_index) = removed_species {
+                app_settings.remove_species(species_index);
+                reset_simulation = true;
+            }
+
+            if ui.button("Add species and Reset").clicked() {
+                app_settings.add_species();
+                reset_simulation = true;
+            }
+
+            ui.collapsing("Species interactions", |ui| {
+                ui.label(
+                    "How much each row species follows (+) or avoids (-) the column species trail",
+                );
+
+                Grid::new("species_interactions").show(ui, |ui| {
+                    ui.label("");
+                    for species_index in 0..app_settings.num_species() {
+                        ui.label(format!("#{}", species_index + 1));
+                    }
+                    ui.end_row();
+
+                    for (species_index, interactions) in
+                        app_settings.species_interactions.iter_mut().enumerate()
+                    {
+                        ui.label(format!("#{}", species_index + 1));
+                        for interaction in interactions.iter_mut() {
+                            settings_updated = settings_updated
+                                || ui.add(DragValue::new(interaction).speed(0.01)).changed();
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+            if reset_simulation {
+                app_should_reset.0 = true;
+            }
+            if settings_updated {
+                app_settings_updated.0 = true;
+            }
+            if step_once {
+                app_step_once.0 = true;
+            }
         });
 }
 
+fn image_format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png8 => "PNG 8 bits",
+        ImageFormat::Png16 => "PNG 16 bits",
+        ImageFormat::Exr => "OpenEXR",
+    }
+}
+
+/// Shape of the diffusion kernel and its parameters, returns whether the kernel changed
+fn add_diffusion_kernel_settings(kernel: &mut DiffusionKernel, ui: &mut Ui) -> bool {
+    let mut changed = false;
+
+    ComboBox::from_label("Diffusion kernel")
+        .selected_text(kernel.name())
+        .show_ui(ui, |ui| {
+            // another shape keeps the reach of the current kernel
+            let radius = kernel.weights().radius;
+            let matrix_size = 2 * radius.min(MAX_MATRIX_RADIUS) as usize + 1;
+
+            for choice in [
+                DiffusionKernel::Box { radius },
+                DiffusionKernel::Gaussian {
+                    sigma: radius.max(1) as f32 / 3.0,
+                },
+                DiffusionKernel::Custom {
+                    weights: vec![vec![1.0; matrix_size]; matrix_size],
+                },
+            ] {
+                let selected = kernel.name() == choice.name();
+                if ui.selectable_label(selected, choice.name()).clicked() && !selected {
+                    *kernel = choice;
+                    changed = true;
+                }
+            }
+        });
+
+    match kernel {
+        DiffusionKernel::Box { radius } => {
+            ui.horizontal(|ui| {
+                let radius_changed = ui
+                    .add(DragValue::new(radius).clamp_range(0..=MAX_KERNEL_RADIUS))
+                    .changed();
+                ui.label("Kernel radius");
+                changed = changed || radius_changed;
+            });
+        }
+        DiffusionKernel::Gaussian { sigma } => {
+            ui.horizontal(|ui| {
+                let sigma_changed = ui
+                    .add(
+                        DragValue::new(sigma)
+                            .speed(0.05)
+                            .clamp_range(0.1..=MAX_KERNEL_RADIUS as f32 / 3.0),
+                    )
+                    .changed();
+                ui.label("Kernel sigma");
+                changed = changed || sigma_changed;
+            });
+        }
+        DiffusionKernel::Custom { weights } => {
+            ui.horizontal(|ui| {
+                let mut radius = (weights.len() / 2) as u32;
+                if ui
+                    .add(DragValue::new(&mut radius).clamp_range(0..=MAX_MATRIX_RADIUS))
+                    .changed()
+                {
+                    *weights = resized_matrix(weights, radius as usize);
+                    changed = true;
+                }
+                ui.label("Kernel radius");
+            });
+
+            Grid::new("diffusion_kernel").show(ui, |ui| {
+                for row in weights.iter_mut() {
+                    for weight in row.iter_mut() {
+                        let weight_changed = ui.add(DragValue::new(weight).speed(0.01)).changed();
+                        changed = changed || weight_changed;
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.label("Weights are normalised to sum to one");
+        }
+    }
+
+    changed
+}
+
+/// Square matrix of the given radius, the weights around the centre are kept
+fn resized_matrix(weights: &[Vec<f32>], radius: usize) -> Vec<Vec<f32>> {
+    let size = 2 * radius + 1;
+    let weight = |row: usize, column: usize| {
+        let row = weights.get((weights.len() / 2 + row).checked_sub(radius)?)?;
+        row.get((row.len() / 2 + column).checked_sub(radius)?)
+            .copied()
+    };
+
+    (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|column| weight(row, column).unwrap_or(0.0))
+                .collect()
+        })
+        .collect()
+}
+
+/// Range, gamma and preview of a gradient palette, and the stops of a custom one. Returns whether
+/// the palette changed
+fn add_palette_editor(app_settings: &mut AppSettings, ui: &mut Ui) -> bool {
+    let gradient = match app_settings.palette.gradient() {
+        Some(gradient) => gradient,
+        None => return false,
+    };
+    let mut changed = false;
+
+    add_gradient_preview(app_settings, &gradient, ui);
+
+    ui.horizontal(|ui| {
+        let max = app_settings.palette_range[1];
+        let min_changed = ui
+            .add(
+                DragValue::new(&mut app_settings.palette_range[0])
+                    .speed(0.01)
+                    .clamp_range(0.0..=(max - MIN_PALETTE_SPAN).max(0.0)),
+            )
+            .changed();
+        let min = app_settings.palette_range[0];
+        ui.label("to");
+        let max_changed = ui
+            .add(
+                DragValue::new(&mut app_settings.palette_range[1])
+                    .speed(0.01)
+                    .clamp_range(min + MIN_PALETTE_SPAN..=f32::INFINITY),
+            )
+            .changed();
+        ui.label("Intensity range");
+        changed = changed || min_changed || max_changed;
+    });
+
+    ui.horizontal(|ui| {
+        let gamma_changed = ui
+            .add(
+                DragValue::new(&mut app_settings.palette_gamma)
+                    .speed(0.01)
+                    .clamp_range(0.05..=10.0),
+            )
+            .changed();
+        ui.label("Gamma");
+        changed = changed || gamma_changed;
+    });
+
+    if let Palette::Custom(gradient) = &mut app_settings.palette {
+        let can_remove = gradient.stops.len() > 2;
+        let mut removed_stop = None;
+
+        for (stop_index, stop) in gradient.stops.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let position_changed = ui
+                    .add(
+                        DragValue::new(&mut stop.position)
+                            .speed(0.005)
+                            .clamp_range(0.0..=1.0),
+                    )
+                    .changed();
+
+                let mut color = srgb8(stop.color);
+                if ui.color_edit_button_srgb(&mut color).changed() {
+                    stop.color = color.map(|component| component as f32 / 255.0);
+                    changed = true;
+                }
+                ui.label("Stop");
+
+                if can_remove && ui.button("Remove").clicked() {
+                    removed_stop = Some(stop_index);
+                }
+                changed = changed || position_changed;
+            });
+        }
+
+        if let Some(stop_index) = removed_stop {
+            gradient.stops.remove(stop_index);
+            changed = true;
+        }
+
+        if ui.button("Add stop").clicked() {
+            gradient.add_stop();
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// The colours of the trail intensities from 0 to the top of the range, or 1 if higher, mapped
+/// through the range and gamma like the display, with a mark where each stop is reached
+fn add_gradient_preview(app_settings: &AppSettings, gradient: &Gradient, ui: &mut Ui) {
+    const SEGMENTS: usize = 64;
+
+    let color_map = color_map_settings(app_settings);
+    let top = app_settings.palette_range[1].max(1.0);
+
+    let (rect, response) = ui.allocate_exact_size(vec2(256.0, 16.0), Sense::hover());
+    response.on_hover_text(format!("Trail intensities from 0 to {:.2}", top));
+    let x = |position: f32| rect.left() + rect.width() * position;
+    let painter = ui.painter();
+
+    for segment in 0..SEGMENTS {
+        let from = segment as f32 / SEGMENTS as f32;
+        let to = (segment + 1) as f32 / SEGMENTS as f32;
+        let intensity = (from + to) / 2.0 * top;
+        let [r, g, b] = srgb8(gradient.sample(gradient_position(&color_map, intensity)));
+
+        painter.rect_filled(
+            Rect::from_min_max(pos2(x(from), rect.top()), pos2(x(to), rect.bottom())),
+            0.0,
+            Color32::from_rgb(r, g, b),
+        );
+    }
+
+    let [min, max] = app_settings.palette_range;
+    for stop in &gradient.stops {
+        // the intensity the gamma maps to this stop
+        let mapped = stop
+            .position
+            .clamp(0.0, 1.0)
+            .powf(1.0 / app_settings.palette_gamma);
+        let stop_x = x(((min + mapped * (max - min)) / top).clamp(0.0, 1.0));
+        painter.line_segment(
+            [pos2(stop_x, rect.top()), pos2(stop_x, rect.bottom())],
+            Stroke::new(1.0, Color32::GRAY),
+        );
+    }
+}
+
+fn srgb8(color: [f32; 3]) -> [u8; 3] {
+    color.map(|component| (component.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+fn recording_format_name(format: RecordingFormat) -> &'static str {
+    match format {
+        RecordingFormat::PngSequence => "PNG sequence",
+        RecordingFormat::Y4m => "Y4M video",
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn add_species_settings(
     species_settings: &mut SpeciesSettings,
+    population: &mut f32,
+    color: &mut [f32; 3],
+    total_population: f32,
+    can_remove: bool,
     changed: &mut bool,
     ui: &mut Ui,
     species_index: usize,
-) {
-    ui.collapsing(format!("Species #{}", species_index + 1), |ui| {
+) -> bool {
+    let mut remove = false;
+
+    let population_percent = if total_population > 0.0 {
+        population.max(0.0) / total_population * 100.0
+    } else {
+        0.0
+    };
+    let header = format!(
+        "Species #{} ({:.0}%)",
+        species_index + 1,
+        population_percent
+    );
+
+    let collapsing = CollapsingHeader::new(header).id_source(("species", species_index));
+
+    collapsing.show(ui, |ui| {
+        ui.horizontal(|ui| {
+            *changed = *changed || ui.color_edit_button_rgb(color).changed();
+            ui.label("Display colour");
+
+            if can_remove && ui.button("Remove and Reset").clicked() {
+                remove = true;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(
+                DragValue::new(population)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+            ui.label("Population share (applied on reset)");
+        });
+
         ui.horizontal(|ui| {
             *changed = *changed
                 || ui
@@ -205,4 +1036,6 @@ fn add_species_settings(
             ui.label("Sensor size");
         });
     });
+
+    remove
 }
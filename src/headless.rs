@@ -0,0 +1,54 @@
+use std::fs;
+
+use crate::{
+    cli::Cli,
+    compute_slime_pipeline::shader_seed,
+    cpu_simulation::CpuSimulation,
+    image_export::ImageFormat,
+    screenshot::save_image,
+    snapshot::Snapshot,
+    types::{AppSettings, TimeBuffer},
+};
+
+/// Runs `cli.steps` fixed steps on the CPU and writes the displayed image every `cli.every` steps,
+/// no window nor GPU is needed. A snapshot continues from its agents, trails and clock
+pub fn run(
+    cli: &Cli,
+    app_settings: &AppSettings,
+    snapshot: Option<&Snapshot>,
+) -> Result<(), String> {
+    fs::create_dir_all(&cli.out)
+        .map_err(|error| format!("Cannot create {}: {}", cli.out.display(), error))?;
+
+    let mut simulation = CpuSimulation::new(app_settings);
+    if let Some(snapshot) = snapshot {
+        simulation.restore(snapshot);
+    }
+
+    let delta_time = app_settings.time_step;
+    let seed = shader_seed(app_settings.seed);
+    let every = cli.every.max(1);
+    let (mut time, first_frame) =
+        snapshot.map_or((0.0, 0), |snapshot| (snapshot.time, snapshot.frame));
+
+    for step_index in 0..cli.steps {
+        simulation.step(&TimeBuffer {
+            time,
+            delta_time,
+            frame: first_frame.wrapping_add(step_index),
+            seed,
+        });
+        time += delta_time;
+
+        let step = step_index + 1;
+        if step % every == 0 || step == cli.steps {
+            let path = cli.out.join(format!("frame_{:06}.png", step));
+            let image = simulation.color_map();
+
+            save_image(&path, ImageFormat::Png8, &image, app_settings)?;
+            println!("{}", path.display());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,316 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Encodings the displayed image can be exported to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    /// 8 bits sRGB PNG, what the window shows
+    Png8,
+    /// 16 bits sRGB PNG, finer gradients, values are still clamped to 1
+    Png16,
+    /// 32 bits float OpenEXR, keeps the linear HDR values of the texture
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png8 | ImageFormat::Png16 => "png",
+            ImageFormat::Exr => "exr",
+        }
+    }
+}
+
+/// Writes linear RGBA pixels, as stored in the display texture, with text metadata given as
+/// key and value pairs
+pub fn write_image(
+    path: &Path,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 4]],
+    metadata: &[(&str, &str)],
+) -> Result<(), String> {
+    let file = File::create(path)
+        .map_err(|error| format!("Cannot create {}: {}", path.display(), error))?;
+    let writer = BufWriter::new(file);
+
+    let written = match format {
+        ImageFormat::Png8 => write_png(writer, width, height, pixels, metadata, false),
+        ImageFormat::Png16 => write_png(writer, width, height, pixels, metadata, true),
+        ImageFormat::Exr => write_exr(writer, width, height, pixels, metadata),
+    };
+
+    written.map_err(|error| format!("Cannot write {}: {}", path.display(), error))
+}
+
+fn write_png(
+    writer: impl Write,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 4]],
+    metadata: &[(&str, &str)],
+    sixteen_bits: bool,
+) -> Result<(), String> {
+    let components = pixels.iter().flat_map(|pixel| {
+        [
+            linear_to_srgb(pixel[0]),
+            linear_to_srgb(pixel[1]),
+            linear_to_srgb(pixel[2]),
+            pixel[3].clamp(0.0, 1.0),
+        ]
+    });
+
+    // 16 bits PNG samples are big endian
+    let data: Vec<u8> = if sixteen_bits {
+        components
+            .flat_map(|component| ((component * 65535.0).round() as u16).to_be_bytes())
+            .collect()
+    } else {
+        components
+            .map(|component| (component * 255.0).round() as u8)
+            .collect()
+    };
+
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(if sixteen_bits {
+        png::BitDepth::Sixteen
+    } else {
+        png::BitDepth::Eight
+    });
+    encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    for (key, value) in metadata {
+        encoder
+            .add_text_chunk(key.to_string(), value.to_string())
+            .map_err(|error| error.to_string())?;
+    }
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(|error| error.to_string())
+}
+
+/// Single part scanline OpenEXR without compression, metadata are string attributes
+fn write_exr(
+    mut writer: impl Write,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 4]],
+    metadata: &[(&str, &str)],
+) -> Result<(), String> {
+    // channels are stored in alphabetical order, each of them a 32 bits float
+    const CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+    const PIXEL_TYPE_FLOAT: i32 = 2;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&20000630_i32.to_le_bytes());
+    header.extend_from_slice(&2_i32.to_le_bytes());
+
+    let mut channels = Vec::new();
+    for (name, _) in CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // linear flag and reserved bytes, then the x and y sampling
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1_i32.to_le_bytes());
+        channels.extend_from_slice(&1_i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+
+    write_exr_attribute(&mut header, "channels", "chlist", &channels);
+    write_exr_attribute(&mut header, "compression", "compression", &[0]);
+    write_exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    write_exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1_f32.to_le_bytes(),
+    );
+    for (key, value) in metadata {
+        write_exr_attribute(&mut header, key, "string", value.as_bytes());
+    }
+    header.push(0);
+
+    // one scanline per block, the offset table points at each of them
+    let row_size = 8 + width as usize * CHANNELS.len() * 4;
+    let first_row_offset = header.len() + height as usize * 8;
+    for y in 0..height as usize {
+        header.extend_from_slice(&((first_row_offset + y * row_size) as u64).to_le_bytes());
+    }
+    writer
+        .write_all(&header)
+        .map_err(|error| error.to_string())?;
+
+    let mut row = Vec::with_capacity(row_size);
+    for (y, pixel_row) in pixels.chunks_exact(width as usize).enumerate() {
+        row.clear();
+        row.extend_from_slice(&(y as i32).to_le_bytes());
+        row.extend_from_slice(&((row_size - 8) as i32).to_le_bytes());
+        for (_, component) in CHANNELS {
+            for pixel in pixel_row {
+                row.extend_from_slice(&pixel[component].to_le_bytes());
+            }
+        }
+        writer.write_all(&row).map_err(|error| error.to_string())?;
+    }
+
+    writer.flush().map_err(|error| error.to_string())
+}
+
+fn write_exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Stream header of an uncompressed 8 bits 4:4:4 YUV4MPEG2 video, the frame rate is a fraction
+pub fn write_y4m_header(
+    mut writer: impl Write,
+    width: u32,
+    height: u32,
+    frame_rate: (u32, u32),
+) -> Result<(), String> {
+    writeln!(
+        writer,
+        "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+        width, height, frame_rate.0, frame_rate.1
+    )
+    .map_err(|error| error.to_string())
+}
+
+/// One video frame of linear RGBA pixels, converted to limited range BT.601 YUV planes
+pub fn write_y4m_frame(mut writer: impl Write, pixels: &[[f32; 4]]) -> Result<(), String> {
+    let mut planes = vec![0; pixels.len() * 3];
+    let (luma, chroma) = planes.split_at_mut(pixels.len());
+    let (blue_difference, red_difference) = chroma.split_at_mut(pixels.len());
+
+    for (index, pixel) in pixels.iter().enumerate() {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(linear_to_srgb);
+
+        luma[index] = (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8;
+        blue_difference[index] = (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8;
+        red_difference[index] = (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8;
+    }
+
+    writer
+        .write_all(b"FRAME\n")
+        .and_then(|_| writer.write_all(&planes))
+        .map_err(|error| error.to_string())
+}
+
+/// The window swapchain encodes the linear display values the same way
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_name(bytes: &[u8], position: &mut usize) -> String {
+        let end = *position + bytes[*position..].iter().position(|&b| b == 0).unwrap();
+        let name = String::from_utf8(bytes[*position..end].to_vec()).unwrap();
+        *position = end + 1;
+        name
+    }
+
+    fn read_i32(bytes: &[u8], position: usize) -> i32 {
+        i32::from_le_bytes(bytes[position..position + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn exr_header_describes_the_pixels() {
+        let pixels = [[0.25, 0.5, 0.75, 1.0], [2.0, 0.0, 0.0, 0.5]];
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, 2, 1, &pixels, &[("Settings", "(seed: 1)")]).unwrap();
+
+        // magic number, then version 2 of a single part scanline file
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(read_i32(&bytes, 4), 2);
+
+        let mut position = 8;
+        let mut attributes = Vec::new();
+        loop {
+            let name = read_name(&bytes, &mut position);
+            if name.is_empty() {
+                break;
+            }
+            let kind = read_name(&bytes, &mut position);
+            let size = read_i32(&bytes, position) as usize;
+            let value = bytes[position + 4..position + 4 + size].to_vec();
+            position += 4 + size;
+            attributes.push((name, kind, value));
+        }
+
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|attribute| attribute.0 == name)
+                .unwrap_or_else(|| panic!("missing {}", name))
+        };
+
+        let (_, kind, channels) = attribute("channels");
+        assert_eq!(kind, "chlist");
+        let mut channel_position = 0;
+        for expected in ["A", "B", "G", "R"] {
+            assert_eq!(read_name(channels, &mut channel_position), expected);
+            // float pixels, linear flag and reserved bytes, then 1x1 sampling
+            assert_eq!(read_i32(channels, channel_position), 2);
+            assert_eq!(channels[channel_position + 4..channel_position + 8], [0; 4]);
+            assert_eq!(read_i32(channels, channel_position + 8), 1);
+            assert_eq!(read_i32(channels, channel_position + 12), 1);
+            channel_position += 16;
+        }
+        assert_eq!(channels[channel_position..], [0]);
+
+        let (_, kind, window) = attribute("dataWindow");
+        assert_eq!(kind, "box2i");
+        let window: Vec<i32> = (0..4).map(|index| read_i32(window, index * 4)).collect();
+        assert_eq!(window, [0, 0, 1, 0]);
+        assert_eq!(attribute("compression").2, [0]);
+        assert_eq!(attribute("Settings").2, b"(seed: 1)");
+
+        // a single scanline, its block follows the offset table
+        let offset = u64::from_le_bytes(bytes[position..position + 8].try_into().unwrap());
+        assert_eq!(offset as usize, position + 8);
+
+        let block = &bytes[offset as usize..];
+        assert_eq!(read_i32(block, 0), 0);
+        assert_eq!(read_i32(block, 4), 2 * 4 * 4);
+        let values: Vec<f32> = block[8..]
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, [1.0, 0.5, 0.75, 0.0, 0.5, 0.0, 0.25, 2.0]);
+    }
+}
@@ -1,21 +1,49 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
 use bevy::{
+    asset::AssetServerSettings,
     prelude::{
-        default, App, Assets, Camera2dBundle, ClearColor, Color, Commands, Image, Res, ResMut, Vec2,
+        default, App, Assets, Camera2dBundle, ClearColor, Color, Commands, CoreStage, Image, Query,
+        Res, ResMut, Vec2,
     },
     render::{extract_resource::ExtractResource, render_resource::*},
     sprite::{Sprite, SpriteBundle},
-    window::WindowDescriptor,
+    window::{WindowDescriptor, WindowMode, Windows},
     DefaultPlugins,
 };
+use clap::Parser;
+use cli::Cli;
 use compute_plugin::{ComputePlugin, ComputeSlimeDisplayImage};
+use compute_render_node::PipelineError;
 use gui_plugin::GuiPlugin;
+use pass_timer::PassTimings;
+use performance_plugin::PerformancePlugin;
+use recording::RecordingSettings;
+use screenshot::ScreenshotRequest;
+use settings_watcher::SettingsWatcherPlugin;
+use snapshot::Snapshot;
 use types::AppSettings;
 
+mod agent_spawner;
+mod cli;
 mod compute_plugin;
 mod compute_render_node;
 mod compute_slime_pipeline;
+mod cpu_simulation;
 mod gui_plugin;
+mod headless;
+mod image_export;
+mod pass_timer;
+mod performance_plugin;
 mod pipeline;
+mod presets;
+mod recording;
+mod screenshot;
+mod settings_watcher;
+mod snapshot;
 mod types;
 
 const SIZE: (u32, u32) = (1280, 720);
@@ -26,41 +54,144 @@ struct AppShouldReset(bool);
 #[derive(Clone, Copy, ExtractResource)]
 struct AppSettingsUpdated(bool);
 
+#[derive(Clone, Copy, ExtractResource)]
+struct AppPaused(bool);
+
+#[derive(Clone, Copy, ExtractResource)]
+struct AppStepOnce(bool);
+
+/// Simulation coordinates new agents are spawned around this frame, if any
+#[derive(Clone, Copy, ExtractResource)]
+struct AgentSpawnCursor(Option<[f32; 2]>);
+
+/// File the simulation state is written to this frame, if any
+#[derive(Clone, ExtractResource)]
+struct AppSaveSnapshot(Option<PathBuf>);
+
+/// Image file the displayed texture is written to this frame, if any
+#[derive(Clone, ExtractResource)]
+struct AppSaveScreenshot(Option<ScreenshotRequest>);
+
+/// Recording the captured frames are written to, from start to stop
+#[derive(Clone, ExtractResource)]
+struct AppRecording(Option<RecordingSettings>);
+
+/// Compute pipeline that failed to compile, written by the render world and shown by the GUI
+#[derive(Clone, Default)]
+struct AppPipelineError(Arc<Mutex<Option<PipelineError>>>);
+
+/// Times the compute passes while the performance overlay is open
+#[derive(Clone, Copy, ExtractResource)]
+struct AppProfiling(bool);
+
+/// Pass timings and agent updates, written by the render world and drained by the diagnostics
+#[derive(Clone, Default)]
+struct AppPassTimings(Arc<Mutex<PassTimings>>);
+
+/// Snapshot the simulation is rebuilt from, along with a reset, if any
+#[derive(Clone, ExtractResource)]
+struct AppRestoreSnapshot(Option<Arc<Snapshot>>);
+
 fn main() {
+    let cli = Cli::parse();
+
+    let snapshot = cli
+        .snapshot
+        .as_ref()
+        .map(|path| Arc::new(Snapshot::load(path).unwrap_or_else(|error| exit_with_error(&error))));
+
+    let app_settings = match &snapshot {
+        Some(snapshot) => snapshot.app_settings.clone(),
+        None => cli
+            .app_settings()
+            .unwrap_or_else(|error| exit_with_error(&error)),
+    };
+
+    if cli.headless {
+        headless::run(&cli, &app_settings, snapshot.as_deref())
+            .unwrap_or_else(|error| exit_with_error(&error));
+        return;
+    }
+
     App::new()
         .insert_resource(ClearColor(Color::BLACK))
         .insert_resource(WindowDescriptor {
-            width: SIZE.0 as f32,
-            height: SIZE.1 as f32,
+            width: app_settings.width as f32,
+            height: app_settings.height as f32,
+            mode: if cli.fullscreen {
+                WindowMode::BorderlessFullscreen
+            } else {
+                WindowMode::Windowed
+            },
+            ..default()
+        })
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: cli.watch_shaders,
             ..default()
         })
-        .insert_resource(AppSettings::default())
+        .insert_resource(app_settings)
         .insert_resource(AppShouldReset(false))
         .insert_resource(AppSettingsUpdated(false))
+        .insert_resource(AppPaused(false))
+        .insert_resource(AppStepOnce(false))
+        .insert_resource(AgentSpawnCursor(None))
+        .insert_resource(AppSaveSnapshot(None))
+        .insert_resource(AppSaveScreenshot(None))
+        .insert_resource(AppRecording(None))
+        .insert_resource(AppPipelineError::default())
+        .insert_resource(AppProfiling(false))
+        .insert_resource(AppPassTimings::default())
+        // the pipeline is created from it, it only lasts until the first frame
+        .insert_resource(AppRestoreSnapshot(snapshot))
         .add_plugins(DefaultPlugins)
-        .add_plugin(GuiPlugin)
+        .add_plugin(GuiPlugin {
+            show_window: cli.show_gui,
+        })
         .add_plugin(ComputePlugin)
+        .add_plugin(SettingsWatcherPlugin)
+        .add_plugin(PerformancePlugin)
         .add_startup_system(setup)
+        .add_system_to_stage(CoreStage::First, clear_pulse_flags)
+        .add_system(fit_simulation_to_window)
+        .add_system(resize_display_image)
         .run();
 }
 
+fn exit_with_error(error: &str) -> ! {
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
 fn setup(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     app_settings: Res<AppSettings>,
+    app_restore_snapshot: Res<AppRestoreSnapshot>,
 ) {
-    let mut image = Image::new_fill(
-        Extent3d {
-            width: app_settings.width,
-            height: app_settings.height,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        &[0, 0, 0, 0, 0, 0, 0, 0],
-        TextureFormat::Rgba16Float,
-    );
-    image.texture_descriptor.usage =
-        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    let size = Extent3d {
+        width: app_settings.width,
+        height: app_settings.height,
+        depth_or_array_layers: 1,
+    };
+    // shown until the simulation draws its first frame
+    let mut image = match &app_restore_snapshot.0 {
+        Some(snapshot) => Image::new(
+            size,
+            TextureDimension::D2,
+            snapshot.display.clone(),
+            TextureFormat::Rgba16Float,
+        ),
+        None => Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[0, 0, 0, 0, 0, 0, 0, 0],
+            TextureFormat::Rgba16Float,
+        ),
+    };
+    image.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING;
     let image_handle = images.add(image);
 
     commands.spawn_bundle(SpriteBundle {
@@ -78,3 +209,85 @@ fn setup(
     commands.insert_resource(ComputeSlimeDisplayImage(image_handle));
     commands.spawn_bundle(Camera2dBundle::default());
 }
+
+/// Reset, settings update, single step, snapshot and screenshot requests only last one frame,
+/// systems raise them after this one
+fn clear_pulse_flags(
+    mut app_should_reset: ResMut<AppShouldReset>,
+    mut app_settings_updated: ResMut<AppSettingsUpdated>,
+    mut app_step_once: ResMut<AppStepOnce>,
+    mut app_save_snapshot: ResMut<AppSaveSnapshot>,
+    mut app_save_screenshot: ResMut<AppSaveScreenshot>,
+    mut app_restore_snapshot: ResMut<AppRestoreSnapshot>,
+) {
+    if app_should_reset.0 {
+        app_should_reset.0 = false;
+    }
+    if app_settings_updated.0 {
+        app_settings_updated.0 = false;
+    }
+    if app_step_once.0 {
+        app_step_once.0 = false;
+    }
+    if app_save_snapshot.0.is_some() {
+        app_save_snapshot.0 = None;
+    }
+    if app_save_screenshot.0.is_some() {
+        app_save_screenshot.0 = None;
+    }
+    if app_restore_snapshot.0.is_some() {
+        app_restore_snapshot.0 = None;
+    }
+}
+
+fn fit_simulation_to_window(windows: Res<Windows>, mut app_settings: ResMut<AppSettings>) {
+    if !app_settings.fit_to_window {
+        return;
+    }
+
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let width = window.width().round() as u32;
+    let height = window.height().round() as u32;
+
+    // a minimized window has no size, the simulation keeps its own
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    if app_settings.width != width || app_settings.height != height {
+        app_settings.width = width;
+        app_settings.height = height;
+    }
+}
+
+/// Reallocates the displayed image when the simulation size changes, the render world resizes the
+/// simulation textures on its side
+fn resize_display_image(
+    app_settings: Res<AppSettings>,
+    compute_slime_display_image: Res<ComputeSlimeDisplayImage>,
+    mut images: ResMut<Assets<Image>>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    let size = Extent3d {
+        width: app_settings.width.max(1),
+        height: app_settings.height.max(1),
+        depth_or_array_layers: 1,
+    };
+
+    match images.get(&compute_slime_display_image.0) {
+        Some(image) if image.texture_descriptor.size != size => {}
+        _ => return,
+    }
+
+    if let Some(image) = images.get_mut(&compute_slime_display_image.0) {
+        image.resize(size);
+    }
+
+    for mut sprite in sprites.iter_mut() {
+        sprite.custom_size = Some(Vec2::new(size.width as f32, size.height as f32));
+    }
+}
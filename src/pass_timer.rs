@@ -0,0 +1,308 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use bevy::render::{
+    render_resource::{
+        Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, MapMode, WgpuFeatures,
+    },
+    renderer::{RenderContext, RenderDevice, RenderQueue},
+};
+// bevy 0.8 does not re-export these
+use wgpu::{Maintain, QuerySet, QuerySetDescriptor, QueryType};
+
+const MAX_TIMED_PASSES: u32 = 512;
+
+const READBACK_BUFFERS: usize = 3;
+
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+pub type FrameTimings = Vec<(&'static str, f64)>;
+
+#[derive(Default)]
+pub struct PassTimings {
+    pub frames: Vec<FrameTimings>,
+    pub agent_updates: u64,
+    pub gpu_timestamps: bool,
+}
+
+/// Times the compute passes of a frame, with timestamp queries when the adapter has them.
+/// Otherwise every pass is submitted on its own and waited for, which stalls the frame, so the
+/// passes are only timed while the overlay is open
+pub struct PassTimer {
+    render_device: RenderDevice,
+    render_queue: RenderQueue,
+    timestamps: Option<TimestampQueries>,
+    state: Mutex<TimerState>,
+}
+
+struct TimestampQueries {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    period: f64,
+}
+
+#[derive(Default)]
+struct TimerState {
+    enabled: bool,
+    passes: Vec<&'static str>,
+    open: bool,
+    started: Option<Instant>,
+    cpu_timings: FrameTimings,
+    slot: Option<usize>,
+    readbacks: Vec<Readback>,
+}
+
+struct Readback {
+    buffer: Buffer,
+    passes: Vec<&'static str>,
+    state: ReadbackState,
+}
+
+enum ReadbackState {
+    Free,
+    /// The copy is submitted at the end of the frame, it is mapped from the next one
+    Copied,
+    Mapping(Arc<Mutex<Option<bool>>>),
+}
+
+impl PassTimer {
+    pub fn new(render_device: &RenderDevice, render_queue: &RenderQueue) -> Self {
+        let timestamps = render_device
+            .features()
+            .contains(WgpuFeatures::TIMESTAMP_QUERY)
+            .then(|| TimestampQueries {
+                query_set: render_device
+                    .wgpu_device()
+                    .create_query_set(&QuerySetDescriptor {
+                        label: Some("pass_timer_query_set"),
+                        ty: QueryType::Timestamp,
+                        count: MAX_TIMED_PASSES * 2,
+                    }),
+                resolve_buffer: render_device.create_buffer(&BufferDescriptor {
+                    label: Some("pass_timer_resolve_buffer"),
+                    size: MAX_TIMED_PASSES as u64 * 2 * TIMESTAMP_SIZE,
+                    usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                period: render_queue.get_timestamp_period() as f64,
+            });
+
+        let readbacks = match timestamps {
+            Some(_) => (0..READBACK_BUFFERS)
+                .map(|_| Readback {
+                    buffer: render_device.create_buffer(&BufferDescriptor {
+                        label: Some("pass_timer_readback_buffer"),
+                        size: MAX_TIMED_PASSES as u64 * 2 * TIMESTAMP_SIZE,
+                        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    }),
+                    passes: Vec::new(),
+                    state: ReadbackState::Free,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        PassTimer {
+            render_device: render_device.clone(),
+            render_queue: render_queue.clone(),
+            timestamps,
+            state: Mutex::new(TimerState {
+                readbacks,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn gpu_timestamps(&self) -> bool {
+        self.timestamps.is_some()
+    }
+
+    /// Timings of the frames read back since the last call, the GPU ones arrive a few frames late
+    pub fn collect(&mut self) -> Vec<FrameTimings> {
+        let state = self.state.get_mut().unwrap();
+        let mut frames = Vec::new();
+
+        if !state.cpu_timings.is_empty() {
+            frames.push(std::mem::take(&mut state.cpu_timings));
+        }
+
+        let timestamps = match &self.timestamps {
+            Some(timestamps) => timestamps,
+            None => return frames,
+        };
+
+        self.render_device.poll(Maintain::Poll);
+
+        for readback in &mut state.readbacks {
+            match &readback.state {
+                ReadbackState::Free => {}
+                ReadbackState::Copied => {
+                    let mapped = Arc::new(Mutex::new(None));
+                    let callback_mapped = mapped.clone();
+                    let slice = readback.buffer.slice(..);
+                    self.render_device
+                        .map_buffer(&slice, MapMode::Read, move |result| {
+                            *callback_mapped.lock().unwrap() = Some(result.is_ok());
+                        });
+                    readback.state = ReadbackState::Mapping(mapped);
+                }
+                ReadbackState::Mapping(mapped) => {
+                    let mapped = *mapped.lock().unwrap();
+                    match mapped {
+                        None => continue,
+                        Some(true) => {
+                            let data = readback.buffer.slice(..).get_mapped_range();
+                            let ticks: &[u64] = bytemuck::cast_slice(&data);
+                            let durations = ticks.chunks_exact(2).map(|pair| {
+                                pair[1].wrapping_sub(pair[0]) as f64 * timestamps.period / 1e6
+                            });
+                            frames.push(sum_by_pass(&readback.passes, durations));
+                            drop(data);
+                            readback.buffer.unmap();
+                        }
+                        // a lost frame of timings, the next ones still come
+                        Some(false) => {}
+                    }
+                    readback.state = ReadbackState::Free;
+                }
+            }
+        }
+
+        frames
+    }
+
+    pub fn begin_frame(&mut self, enabled: bool) {
+        let state = self.state.get_mut().unwrap();
+        state.enabled = enabled;
+        state.passes.clear();
+        state.open = false;
+        state.started = None;
+        state.cpu_timings.clear();
+        state.slot = if enabled {
+            state
+                .readbacks
+                .iter()
+                .position(|readback| matches!(readback.state, ReadbackState::Free))
+        } else {
+            None
+        };
+    }
+
+    pub fn start_pass(&self, render_context: &mut RenderContext, name: &'static str) {
+        let mut state = self.state.lock().unwrap();
+        if !state.enabled {
+            return;
+        }
+
+        match &self.timestamps {
+            Some(timestamps) => {
+                let index = state.passes.len() as u32;
+                if state.slot.is_none() || index >= MAX_TIMED_PASSES {
+                    return;
+                }
+
+                render_context
+                    .command_encoder
+                    .write_timestamp(&timestamps.query_set, index * 2);
+            }
+            None => {
+                // the passes recorded before this one are not part of it
+                self.submit_and_wait(render_context);
+                state.started = Some(Instant::now());
+            }
+        }
+
+        state.passes.push(name);
+        state.open = true;
+    }
+
+    pub fn end_pass(&self, render_context: &mut RenderContext) {
+        let mut state = self.state.lock().unwrap();
+        if !state.open {
+            return;
+        }
+        state.open = false;
+
+        match &self.timestamps {
+            Some(timestamps) => {
+                let index = state.passes.len() as u32 - 1;
+                render_context
+                    .command_encoder
+                    .write_timestamp(&timestamps.query_set, index * 2 + 1);
+            }
+            None => {
+                self.submit_and_wait(render_context);
+                if let (Some(started), Some(&name)) = (state.started.take(), state.passes.last()) {
+                    let duration = started.elapsed().as_secs_f64() * 1e3;
+                    state.cpu_timings.push((name, duration));
+                }
+            }
+        }
+    }
+
+    pub fn end_frame(&self, render_context: &mut RenderContext) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        if !state.enabled {
+            return;
+        }
+
+        if self.timestamps.is_none() {
+            state.cpu_timings = sum_by_pass(
+                &state.passes,
+                state.cpu_timings.iter().map(|&(_, duration)| duration),
+            );
+            return;
+        }
+
+        let (timestamps, slot) = match (&self.timestamps, state.slot) {
+            (Some(timestamps), Some(slot)) if !state.passes.is_empty() => (timestamps, slot),
+            _ => return,
+        };
+
+        let count = state.passes.len() as u32 * 2;
+        let readback = &mut state.readbacks[slot];
+        render_context.command_encoder.resolve_query_set(
+            &timestamps.query_set,
+            0..count,
+            &timestamps.resolve_buffer,
+            0,
+        );
+        render_context.command_encoder.copy_buffer_to_buffer(
+            &timestamps.resolve_buffer,
+            0,
+            &readback.buffer,
+            0,
+            count as u64 * TIMESTAMP_SIZE,
+        );
+        readback.passes = state.passes.clone();
+        readback.state = ReadbackState::Copied;
+    }
+
+    fn submit_and_wait(&self, render_context: &mut RenderContext) {
+        let command_encoder = std::mem::replace(
+            &mut render_context.command_encoder,
+            self.render_device
+                .create_command_encoder(&CommandEncoderDescriptor::default()),
+        );
+        self.render_queue.submit([command_encoder.finish()]);
+        self.render_device.poll(Maintain::Wait);
+    }
+}
+
+fn sum_by_pass(passes: &[&'static str], durations: impl Iterator<Item = f64>) -> FrameTimings {
+    let mut timings: FrameTimings = Vec::new();
+
+    for (&name, duration) in passes.iter().zip(durations) {
+        match timings.iter_mut().find(|(pass, _)| *pass == name) {
+            Some((_, total)) => *total += duration,
+            None => timings.push((name, duration)),
+        }
+    }
+
+    timings
+}
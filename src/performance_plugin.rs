@@ -0,0 +1,177 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, FrameTimeDiagnosticsPlugin},
+    prelude::{App, Input, KeyCode, Plugin, Res, ResMut, Time},
+};
+use bevy_egui::{
+    egui::{Align2, Grid, Window},
+    EguiContext,
+};
+
+use crate::{AppPassTimings, AppProfiling};
+
+/// Measurements the rolling averages are taken over
+const HISTORY_LENGTH: usize = 120;
+
+/// Pass diagnostics are this id mixed with a hash of the pass name
+const PASS_DIAGNOSTIC_BASE: u128 = 0x6e3a_51c2_84d7_4f0b_9a1e_0000_0000_0000;
+
+pub const AGENTS_PER_SECOND: DiagnosticId =
+    DiagnosticId::from_u128(0x1f4b_2d8e_a6c3_47e9_b5d0_7c21_93fa_6e58);
+
+pub struct PerformancePlugin;
+
+impl Plugin for PerformancePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .init_resource::<TimedPasses>()
+            .add_startup_system(setup_diagnostics)
+            .add_system(toggle_profiling_on_key)
+            .add_system(update_pass_diagnostics)
+            .add_system(performance_overlay_system);
+    }
+}
+
+/// Passes with a diagnostic, in the order they first ran
+#[derive(Default)]
+struct TimedPasses {
+    passes: Vec<&'static str>,
+    gpu_timestamps: bool,
+}
+
+pub fn pass_diagnostic_id(pass: &str) -> DiagnosticId {
+    let mut hasher = DefaultHasher::new();
+    pass.hash(&mut hasher);
+    DiagnosticId::from_u128(PASS_DIAGNOSTIC_BASE | u128::from(hasher.finish()))
+}
+
+fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(
+        Diagnostic::new(AGENTS_PER_SECOND, "agents_per_second", HISTORY_LENGTH)
+            .with_suffix("agents/s"),
+    );
+}
+
+fn toggle_profiling_on_key(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut app_profiling: ResMut<AppProfiling>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        app_profiling.0 = !app_profiling.0;
+    }
+}
+
+/// Turns the timings of the render world into diagnostics, starting over each time the overlay
+/// opens
+fn update_pass_diagnostics(
+    time: Res<Time>,
+    app_profiling: Res<AppProfiling>,
+    app_pass_timings: Res<AppPassTimings>,
+    mut timed_passes: ResMut<TimedPasses>,
+    mut diagnostics: ResMut<Diagnostics>,
+) {
+    let timings = std::mem::take(&mut *app_pass_timings.0.lock().unwrap());
+
+    if app_profiling.is_changed() && app_profiling.0 {
+        for &pass in &timed_passes.passes {
+            if let Some(diagnostic) = diagnostics.get_mut(pass_diagnostic_id(pass)) {
+                diagnostic.clear_history();
+            }
+        }
+        if let Some(diagnostic) = diagnostics.get_mut(AGENTS_PER_SECOND) {
+            diagnostic.clear_history();
+        }
+    }
+
+    if !app_profiling.0 {
+        return;
+    }
+
+    timed_passes.gpu_timestamps = timings.gpu_timestamps;
+
+    for frame in timings.frames {
+        for (pass, duration) in frame {
+            let id = pass_diagnostic_id(pass);
+            if !timed_passes.passes.contains(&pass) {
+                timed_passes.passes.push(pass);
+                diagnostics.add(Diagnostic::new(id, pass, HISTORY_LENGTH).with_suffix("ms"));
+            }
+
+            diagnostics.add_measurement(id, || duration);
+        }
+    }
+
+    let delta_seconds = time.delta_seconds_f64();
+    if delta_seconds > 0.0 {
+        diagnostics.add_measurement(AGENTS_PER_SECOND, || {
+            timings.agent_updates as f64 / delta_seconds
+        });
+    }
+}
+
+fn performance_overlay_system(
+    mut egui_context: ResMut<EguiContext>,
+    mut app_profiling: ResMut<AppProfiling>,
+    timed_passes: Res<TimedPasses>,
+    diagnostics: Res<Diagnostics>,
+) {
+    if !app_profiling.0 {
+        return;
+    }
+
+    let average = |id| diagnostics.get(id).and_then(Diagnostic::average);
+    let mut is_open = true;
+
+    Window::new("Performance")
+        .anchor(Align2::RIGHT_TOP, [-10.0, 10.0])
+        .resizable(false)
+        .open(&mut is_open)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(if timed_passes.gpu_timestamps {
+                "GPU timestamps"
+            } else {
+                "No timestamp queries, every pass is waited for on the CPU"
+            });
+
+            Grid::new("pass_timings").num_columns(2).show(ui, |ui| {
+                let mut total = 0.0;
+                for &pass in &timed_passes.passes {
+                    ui.label(pass);
+                    match average(pass_diagnostic_id(pass)) {
+                        Some(duration) => {
+                            total += duration;
+                            ui.monospace(format!("{:.3} ms", duration));
+                        }
+                        None => {
+                            ui.monospace("-");
+                        }
+                    }
+                    ui.end_row();
+                }
+
+                ui.strong("Passes");
+                ui.monospace(format!("{:.3} ms", total));
+                ui.end_row();
+
+                if let Some(frame_time) = average(FrameTimeDiagnosticsPlugin::FRAME_TIME) {
+                    ui.strong("Frame");
+                    ui.monospace(format!("{:.3} ms", frame_time * 1e3));
+                    ui.end_row();
+                }
+
+                if let Some(agents_per_second) = average(AGENTS_PER_SECOND) {
+                    ui.strong("Agents updated");
+                    ui.monospace(format!("{:.1} M/s", agents_per_second / 1e6));
+                    ui.end_row();
+                }
+            });
+        });
+
+    if !is_open {
+        app_profiling.0 = false;
+    }
+}
@@ -6,11 +6,17 @@ use bevy::{
     },
 };
 
-pub mod copy_pipeline;
+pub mod color_map_pipeline;
 pub mod diffuse_pipeline;
 pub mod draw_sensor_pipeline;
+pub mod pass_graph;
+pub mod resample_pipeline;
 pub mod slime_sim_pipeline;
 
+/// Side of the ping-pong texture pairs a step reads the trails from, the trails of the next step
+/// are written to the other side
+pub type PingPongSide = usize;
+
 pub trait Pipeline<'a> {
     type CreationSettings;
     type BindGroupSettings;
@@ -36,6 +42,7 @@ pub trait Pipeline<'a> {
 use crate::compute_render_node::ComputeSlimeState;
 
 pub use self::{
-    copy_pipeline::CopyPipeline, diffuse_pipeline::DiffusePipeline,
-    draw_sensor_pipeline::DrawSensorPipeline, slime_sim_pipeline::SlimeSimPipeline,
+    color_map_pipeline::ColorMapPipeline, diffuse_pipeline::DiffusePipeline,
+    draw_sensor_pipeline::DrawSensorPipeline, resample_pipeline::ResamplePipeline,
+    slime_sim_pipeline::SlimeSimPipeline,
 };
@@ -0,0 +1,265 @@
+use std::borrow::Cow;
+
+use bevy::{
+    prelude::{AssetServer, Handle, World},
+    render::{
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+
+use crate::{
+    compute_render_node::ComputeSlimeState,
+    pipeline::{
+        pass_graph::{ComputePass, PassContext, PassResources, PassSchedule},
+        PingPongSide, Pipeline,
+    },
+    types::{ColorMapSettings, SizeSettings},
+};
+
+const COLOR_MAP_SIZE: f32 = 8.0;
+
+pub struct ColorMapPipeline {
+    /// One per ping-pong side
+    pub bind_groups: [Option<BindGroup>; 2],
+    pub bind_group_layout: BindGroupLayout,
+    pub color_map_pipeline_id: CachedComputePipelineId,
+    pub workgroup_size: (u32, u32),
+}
+
+pub struct ColorMapBuffers<'a> {
+    pub side: PingPongSide,
+    pub size_buffer: Buffer,
+    pub color_map_buffer: Buffer,
+    pub species_colors_buffer: Buffer,
+    pub texture_view_read: &'a TextureView,
+    pub texture_view_write: &'a TextureView,
+}
+
+impl ColorMapPipeline {
+    /// Follows the textures once they are reallocated at another size
+    pub fn set_size(&mut self, settings: &SizeSettings) {
+        self.workgroup_size = (
+            (settings.width as f32 / COLOR_MAP_SIZE).ceil() as u32,
+            (settings.height as f32 / COLOR_MAP_SIZE).ceil() as u32,
+        );
+    }
+}
+
+impl<'a> Pipeline<'a> for ColorMapPipeline {
+    type CreationSettings = SizeSettings;
+    type BindGroupSettings = ColorMapBuffers<'a>;
+    type ExecuteSettings = PingPongSide;
+
+    fn new(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let settings = world.resource::<SizeSettings>();
+
+        let bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<SizeSettings>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<ColorMapSettings>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::ReadOnly,
+                            format: TextureFormat::R32Float,
+                            view_dimension: TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: TextureFormat::Rgba16Float,
+                            view_dimension: TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<[f32; 4]>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let asset_server = world.resource::<AssetServer>();
+        let shader: Handle<Shader> = asset_server.load("color_map.wgsl");
+
+        let workgroup_size = (
+            (settings.width as f32 / COLOR_MAP_SIZE).ceil() as u32,
+            (settings.height as f32 / COLOR_MAP_SIZE).ceil() as u32,
+        );
+
+        let mut pipeline_cache = world.resource_mut::<PipelineCache>();
+        let color_map_pipeline_id =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: Some(vec![bind_group_layout.clone()]),
+                shader,
+                shader_defs: vec![],
+                entry_point: Cow::from("color_map_main"),
+            });
+
+        ColorMapPipeline {
+            bind_groups: [None, None],
+            bind_group_layout,
+            color_map_pipeline_id,
+            workgroup_size,
+        }
+    }
+
+    fn queue_bind_group(&mut self, render_device: &RenderDevice, settings: &ColorMapBuffers) {
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: settings.size_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: settings.color_map_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(settings.texture_view_read),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(settings.texture_view_write),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: settings.species_colors_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        self.bind_groups[settings.side] = Some(bind_group);
+    }
+
+    fn execute(
+        &self,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        _state: &ComputeSlimeState,
+        side: &Self::ExecuteSettings,
+    ) {
+        match &self.bind_groups[*side] {
+            Some(bind_group) => {
+                render_context
+                    .command_encoder
+                    .push_debug_group("Execute color map pipeline");
+                {
+                    let mut pass = render_context
+                        .command_encoder
+                        .begin_compute_pass(&ComputePassDescriptor::default());
+
+                    pass.set_bind_group(0, bind_group, &[]);
+
+                    let color_map_pipeline = pipeline_cache
+                        .get_compute_pipeline(self.color_map_pipeline_id)
+                        .unwrap();
+
+                    pass.set_pipeline(color_map_pipeline);
+                    pass.dispatch_workgroups(self.workgroup_size.0, self.workgroup_size.1, 1);
+                }
+                render_context.command_encoder.pop_debug_group();
+            }
+            None => panic!("Bind group not set"),
+        }
+    }
+}
+
+impl ComputePass for ColorMapPipeline {
+    fn name(&self) -> &'static str {
+        "colour map"
+    }
+
+    fn schedule(&self) -> PassSchedule {
+        PassSchedule::Frame
+    }
+
+    fn inputs(&self) -> &'static [&'static str] {
+        &["size", "color_map", "species_colors", "display"]
+    }
+
+    fn outputs(&self) -> &'static [&'static str] {
+        &["render_display"]
+    }
+
+    fn pipeline_ids(&self) -> Vec<CachedComputePipelineId> {
+        vec![self.color_map_pipeline_id]
+    }
+
+    fn set_size(&mut self, size: &SizeSettings) {
+        ColorMapPipeline::set_size(self, size);
+    }
+
+    fn queue_pass_bind_group(
+        &mut self,
+        render_device: &RenderDevice,
+        resources: &PassResources,
+        side: PingPongSide,
+    ) -> Result<(), String> {
+        let buffers = ColorMapBuffers {
+            side,
+            size_buffer: resources.buffer("size")?.clone(),
+            color_map_buffer: resources.buffer("color_map")?.clone(),
+            species_colors_buffer: resources.buffer("species_colors")?.clone(),
+            texture_view_read: resources.texture("display", side)?,
+            texture_view_write: resources.texture("render_display", side)?,
+        };
+
+        self.queue_bind_group(render_device, &buffers);
+
+        Ok(())
+    }
+
+    fn execute_pass(
+        &self,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        state: &ComputeSlimeState,
+        context: &PassContext,
+    ) {
+        self.execute(render_context, pipeline_cache, state, &context.side);
+    }
+}
@@ -10,96 +10,105 @@ use bevy::{
 
 use crate::{
     compute_render_node::ComputeSlimeState,
-    pipeline::Pipeline,
+    pipeline::{
+        pass_graph::{ComputePass, PassContext, PassResources},
+        PingPongSide, Pipeline,
+    },
     types::{DiffuseSettings, SizeSettings, TimeBuffer},
 };
 
 const DIFFUSE_SIZE: f32 = 8.0;
 
 pub struct DiffusePipeline {
-    pub bind_group: Option<BindGroup>,
+    /// One of each per ping-pong side
+    pub blur_bind_groups: [Option<BindGroup>; 2],
+    pub bind_groups: [Option<BindGroup>; 2],
+    pub blur_bind_group_layout: BindGroupLayout,
     pub bind_group_layout: BindGroupLayout,
+    pub diffuse_blur_pipeline_id: CachedComputePipelineId,
     pub diffuse_pipeline_id: CachedComputePipelineId,
     pub workgroup_size: (u32, u32),
 }
 
 pub struct DiffuseBuffers<'a> {
+    pub side: PingPongSide,
     pub size_buffer: Buffer,
     pub diffuse_buffer: Buffer,
     pub time_buffer: Buffer,
     pub trail_texture: &'a TextureView,
+    /// Rgba32Float texture between the two passes
+    pub blur_texture: &'a TextureView,
+    /// Trails of the next step, displayed and sensed by the agents
     pub diffuse_texture: &'a TextureView,
+    /// Same trails again, the agents of the next step deposit onto them
+    pub next_trail_texture: &'a TextureView,
+}
+
+impl DiffusePipeline {
+    /// Follows the textures once they are reallocated at another size
+    pub fn set_size(&mut self, settings: &SizeSettings) {
+        self.workgroup_size = (
+            (settings.width as f32 / DIFFUSE_SIZE).ceil() as u32,
+            (settings.height as f32 / DIFFUSE_SIZE).ceil() as u32,
+        );
+    }
 }
 
 impl<'a> Pipeline<'a> for DiffusePipeline {
     type CreationSettings = SizeSettings;
     type BindGroupSettings = DiffuseBuffers<'a>;
-    type ExecuteSettings = ();
+    type ExecuteSettings = PingPongSide;
 
     fn new(world: &mut World) -> Self {
         let render_device = world.resource::<RenderDevice>();
         let settings = world.resource::<SizeSettings>();
 
+        let uniform_entry = |binding: u32, size: usize| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(size as u64),
+            },
+            count: None,
+        };
+        // one layer per species
+        let texture_entry = |binding: u32, access: StorageTextureAccess| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access,
+                format: TextureFormat::R32Float,
+                view_dimension: TextureViewDimension::D2Array,
+            },
+            count: None,
+        };
+
+        // the blur texture is written by the first pass and read by the second, each of them
+        // binds it once
+        let blur_bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    uniform_entry(0, std::mem::size_of::<SizeSettings>()),
+                    uniform_entry(1, std::mem::size_of::<DiffuseSettings>()),
+                    texture_entry(3, StorageTextureAccess::ReadOnly),
+                    texture_entry(4, StorageTextureAccess::WriteOnly),
+                ],
+            });
+
         let bind_group_layout =
             render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: None,
                 entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::Buffer {
-                            ty: BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: BufferSize::new(
-                                std::mem::size_of::<SizeSettings>() as u64
-                            ),
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::Buffer {
-                            ty: BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: BufferSize::new(
-                                std::mem::size_of::<DiffuseSettings>() as u64,
-                            ),
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::Buffer {
-                            ty: BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: BufferSize::new(
-                                std::mem::size_of::<TimeBuffer>() as u64
-                            ),
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::StorageTexture {
-                            access: StorageTextureAccess::ReadOnly,
-                            format: TextureFormat::Rgba16Float,
-                            view_dimension: TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 4,
-                        visibility: ShaderStages::COMPUTE,
-                        ty: BindingType::StorageTexture {
-                            access: StorageTextureAccess::WriteOnly,
-                            format: TextureFormat::Rgba16Float,
-                            view_dimension: TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
+                    uniform_entry(0, std::mem::size_of::<SizeSettings>()),
+                    uniform_entry(1, std::mem::size_of::<DiffuseSettings>()),
+                    uniform_entry(2, std::mem::size_of::<TimeBuffer>()),
+                    texture_entry(3, StorageTextureAccess::ReadOnly),
+                    texture_entry(5, StorageTextureAccess::ReadOnly),
+                    texture_entry(6, StorageTextureAccess::WriteOnly),
+                    texture_entry(7, StorageTextureAccess::WriteOnly),
                 ],
             });
 
@@ -112,24 +121,58 @@ impl<'a> Pipeline<'a> for DiffusePipeline {
         );
 
         let mut pipeline_cache = world.resource_mut::<PipelineCache>();
+        let diffuse_blur_pipeline_id =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: Some(vec![blur_bind_group_layout.clone()]),
+                shader: shader.clone(),
+                shader_defs: vec![],
+                entry_point: Cow::from("diffuse_blur_main"),
+            });
         let diffuse_pipeline_id =
             pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
                 label: None,
                 layout: Some(vec![bind_group_layout.clone()]),
-                shader: shader.clone(),
+                shader,
                 shader_defs: vec![],
                 entry_point: Cow::from("diffuse_main"),
             });
 
         DiffusePipeline {
-            bind_group: None,
+            blur_bind_groups: [None, None],
+            bind_groups: [None, None],
+            blur_bind_group_layout,
             bind_group_layout,
+            diffuse_blur_pipeline_id,
             diffuse_pipeline_id,
             workgroup_size,
         }
     }
 
     fn queue_bind_group(&mut self, render_device: &RenderDevice, settings: &DiffuseBuffers) {
+        let blur_bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.blur_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: settings.size_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: settings.diffuse_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(settings.trail_texture),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(settings.blur_texture),
+                },
+            ],
+        });
+
         let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
             label: None,
             layout: &self.bind_group_layout,
@@ -148,16 +191,25 @@ impl<'a> Pipeline<'a> for DiffusePipeline {
                 },
                 BindGroupEntry {
                     binding: 3,
-                    resource: BindingResource::TextureView(&settings.trail_texture),
+                    resource: BindingResource::TextureView(settings.trail_texture),
                 },
                 BindGroupEntry {
-                    binding: 4,
-                    resource: BindingResource::TextureView(&settings.diffuse_texture),
+                    binding: 5,
+                    resource: BindingResource::TextureView(settings.blur_texture),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(settings.diffuse_texture),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(settings.next_trail_texture),
                 },
             ],
         });
 
-        self.bind_group = Some(bind_group);
+        self.blur_bind_groups[settings.side] = Some(blur_bind_group);
+        self.bind_groups[settings.side] = Some(bind_group);
     }
 
     fn execute(
@@ -165,10 +217,10 @@ impl<'a> Pipeline<'a> for DiffusePipeline {
         render_context: &mut RenderContext,
         pipeline_cache: &PipelineCache,
         _state: &ComputeSlimeState,
-        _execute_settings: &Self::ExecuteSettings,
+        side: &Self::ExecuteSettings,
     ) {
-        match &self.bind_group {
-            Some(bind_group) => {
+        match (&self.blur_bind_groups[*side], &self.bind_groups[*side]) {
+            (Some(blur_bind_group), Some(bind_group)) => {
                 render_context
                     .command_encoder
                     .push_debug_group("Execute diffuse pipeline");
@@ -177,18 +229,79 @@ impl<'a> Pipeline<'a> for DiffusePipeline {
                         .command_encoder
                         .begin_compute_pass(&ComputePassDescriptor::default());
 
-                    pass.set_bind_group(0, &bind_group, &[]);
+                    let diffuse_blur_pipeline = pipeline_cache
+                        .get_compute_pipeline(self.diffuse_blur_pipeline_id)
+                        .unwrap();
+
+                    pass.set_bind_group(0, blur_bind_group, &[]);
+                    pass.set_pipeline(diffuse_blur_pipeline);
+                    pass.dispatch_workgroups(self.workgroup_size.0, self.workgroup_size.1, 1);
 
                     let diffuse_pipeline = pipeline_cache
                         .get_compute_pipeline(self.diffuse_pipeline_id)
                         .unwrap();
 
+                    pass.set_bind_group(0, bind_group, &[]);
                     pass.set_pipeline(diffuse_pipeline);
                     pass.dispatch_workgroups(self.workgroup_size.0, self.workgroup_size.1, 1);
                 }
                 render_context.command_encoder.pop_debug_group();
             }
-            None => panic!("Bind group not set"),
+            _ => panic!("Bind group not set"),
         }
     }
 }
+
+impl ComputePass for DiffusePipeline {
+    fn name(&self) -> &'static str {
+        "diffuse"
+    }
+
+    fn inputs(&self) -> &'static [&'static str] {
+        &["size", "diffuse_settings", "time", "trail"]
+    }
+
+    fn outputs(&self) -> &'static [&'static str] {
+        &["blur", "next_display", "next_trail"]
+    }
+
+    fn pipeline_ids(&self) -> Vec<CachedComputePipelineId> {
+        vec![self.diffuse_blur_pipeline_id, self.diffuse_pipeline_id]
+    }
+
+    fn set_size(&mut self, size: &SizeSettings) {
+        DiffusePipeline::set_size(self, size);
+    }
+
+    fn queue_pass_bind_group(
+        &mut self,
+        render_device: &RenderDevice,
+        resources: &PassResources,
+        side: PingPongSide,
+    ) -> Result<(), String> {
+        let buffers = DiffuseBuffers {
+            side,
+            size_buffer: resources.buffer("size")?.clone(),
+            diffuse_buffer: resources.buffer("diffuse_settings")?.clone(),
+            time_buffer: resources.buffer("time")?.clone(),
+            trail_texture: resources.texture("trail", side)?,
+            blur_texture: resources.texture("blur", side)?,
+            diffuse_texture: resources.texture("next_display", side)?,
+            next_trail_texture: resources.texture("next_trail", side)?,
+        };
+
+        self.queue_bind_group(render_device, &buffers);
+
+        Ok(())
+    }
+
+    fn execute_pass(
+        &self,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        state: &ComputeSlimeState,
+        context: &PassContext,
+    ) {
+        self.execute(render_context, pipeline_cache, state, &context.side);
+    }
+}
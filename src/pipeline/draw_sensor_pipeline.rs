@@ -10,38 +10,42 @@ use bevy::{
 
 use crate::{
     compute_render_node::ComputeSlimeState,
-    pipeline::Pipeline,
-    types::{Agent, SizeSettings, SlimeSettings},
+    pipeline::{
+        pass_graph::{ComputePass, PassContext, PassResources, PassSchedule},
+        PingPongSide, Pipeline,
+    },
+    types::{Agent, SizeSettings, SlimeSettings, SpeciesSettings},
 };
 
-use super::slime_sim_pipeline::SlimeSimSetup;
+use super::slime_sim_pipeline::SlimeSimExecute;
 
 const PARTICLES_PER_GROUP: usize = 64;
 
 pub struct DrawSensorPipeline {
-    pub bind_group: Option<BindGroup>,
+    /// One per ping-pong side
+    pub bind_groups: [Option<BindGroup>; 2],
     pub bind_group_layout: BindGroupLayout,
     pub draw_sensor_pipeline_id: CachedComputePipelineId,
-    pub workgroup_size: u32,
 }
 
 #[derive(Debug)]
 pub struct DrawSensorBuffers<'a> {
+    pub side: PingPongSide,
     pub texture_view_read: &'a TextureView,
     pub texture_view_write: &'a TextureView,
     pub agents_buffer: Buffer,
     pub settings_buffer: Buffer,
     pub size_buffers: Buffer,
+    pub species_buffer: Buffer,
 }
 
 impl<'a> Pipeline<'a> for DrawSensorPipeline {
-    type CreationSettings = SlimeSimSetup;
+    type CreationSettings = ();
     type BindGroupSettings = DrawSensorBuffers<'a>;
-    type ExecuteSettings = ();
+    type ExecuteSettings = SlimeSimExecute;
 
     fn new(world: &mut World) -> Self {
         let render_device = world.resource::<RenderDevice>();
-        let settings = world.resource::<Self::CreationSettings>();
 
         let bind_group_layout =
             render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -77,10 +81,7 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
                         ty: BindingType::Buffer {
                             ty: BufferBindingType::Storage { read_only: false },
                             has_dynamic_offset: false,
-                            min_binding_size: BufferSize::new(
-                                (std::mem::size_of::<Agent>() * settings.num_agents as usize)
-                                    as u64,
-                            ),
+                            min_binding_size: BufferSize::new(std::mem::size_of::<Agent>() as u64),
                         },
                         count: None,
                     },
@@ -89,8 +90,8 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
                         visibility: ShaderStages::COMPUTE,
                         ty: BindingType::StorageTexture {
                             access: StorageTextureAccess::ReadOnly,
-                            format: TextureFormat::Rgba16Float,
-                            view_dimension: TextureViewDimension::D2,
+                            format: TextureFormat::R32Float,
+                            view_dimension: TextureViewDimension::D2Array,
                         },
                         count: None,
                     },
@@ -104,15 +105,24 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
                         },
                         count: None,
                     },
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<SpeciesSettings>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
                 ],
             });
 
         let asset_server = world.resource::<AssetServer>();
         let shader: Handle<Shader> = asset_server.load("draw_sensor.wgsl");
 
-        let workgroup_size =
-            ((settings.num_agents as f32) / (PARTICLES_PER_GROUP as f32)).ceil() as u32;
-
         let mut pipeline_cache = world.resource_mut::<PipelineCache>();
         let draw_sensor_pipeline_id =
             pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
@@ -124,10 +134,9 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
             });
 
         Self {
-            bind_group: None,
+            bind_groups: [None, None],
             bind_group_layout,
             draw_sensor_pipeline_id,
-            workgroup_size,
         }
     }
 
@@ -156,10 +165,14 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
                     binding: 4,
                     resource: BindingResource::TextureView(&buffers.texture_view_write),
                 },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: buffers.species_buffer.as_entire_binding(),
+                },
             ],
         });
 
-        self.bind_group = Some(bind_group);
+        self.bind_groups[buffers.side] = Some(bind_group);
     }
 
     fn execute(
@@ -167,9 +180,16 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
         render_context: &mut RenderContext,
         pipeline_cache: &PipelineCache,
         _state: &ComputeSlimeState,
-        _execute_settings: &Self::ExecuteSettings,
+        execute_settings: &Self::ExecuteSettings,
     ) {
-        match &self.bind_group {
+        let workgroup_size =
+            ((execute_settings.num_agents as f32) / (PARTICLES_PER_GROUP as f32)).ceil() as u32;
+
+        if workgroup_size == 0 {
+            return;
+        }
+
+        match &self.bind_groups[execute_settings.side] {
             Some(bind_group) => {
                 render_context
                     .command_encoder
@@ -186,7 +206,7 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
                         .unwrap();
 
                     pass.set_pipeline(draw_sensor_pipeline);
-                    pass.dispatch_workgroups(self.workgroup_size, 1, 1);
+                    pass.dispatch_workgroups(workgroup_size, 1, 1);
                 }
                 render_context.command_encoder.pop_debug_group();
             }
@@ -194,3 +214,73 @@ impl<'a> Pipeline<'a> for DrawSensorPipeline {
         }
     }
 }
+
+impl ComputePass for DrawSensorPipeline {
+    fn name(&self) -> &'static str {
+        "draw sensors"
+    }
+
+    fn schedule(&self) -> PassSchedule {
+        PassSchedule::Frame
+    }
+
+    fn inputs(&self) -> &'static [&'static str] {
+        &[
+            "size",
+            "slime_settings",
+            "agents",
+            "species",
+            "display",
+            "render_display",
+        ]
+    }
+
+    /// Drawn over the colour map
+    fn outputs(&self) -> &'static [&'static str] {
+        &["render_display"]
+    }
+
+    fn pipeline_ids(&self) -> Vec<CachedComputePipelineId> {
+        vec![self.draw_sensor_pipeline_id]
+    }
+
+    fn queue_pass_bind_group(
+        &mut self,
+        render_device: &RenderDevice,
+        resources: &PassResources,
+        side: PingPongSide,
+    ) -> Result<(), String> {
+        let buffers = DrawSensorBuffers {
+            side,
+            size_buffers: resources.buffer("size")?.clone(),
+            settings_buffer: resources.buffer("slime_settings")?.clone(),
+            agents_buffer: resources.buffer("agents")?.clone(),
+            species_buffer: resources.buffer("species")?.clone(),
+            texture_view_read: resources.texture("display", side)?,
+            texture_view_write: resources.texture("render_display", side)?,
+        };
+
+        self.queue_bind_group(render_device, &buffers);
+
+        Ok(())
+    }
+
+    fn execute_pass(
+        &self,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        state: &ComputeSlimeState,
+        context: &PassContext,
+    ) {
+        if !context.render_sensors {
+            return;
+        }
+
+        let execute_settings = SlimeSimExecute {
+            num_agents: context.num_agents,
+            side: context.side,
+        };
+
+        self.execute(render_context, pipeline_cache, state, &execute_settings);
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::{App, World},
+    render::{
+        render_resource::{Buffer, CachedComputePipelineId, PipelineCache, TextureView},
+        renderer::{RenderContext, RenderDevice},
+        RenderApp,
+    },
+};
+
+use crate::{
+    compute_render_node::{ComputeSlimeState, PipelineError},
+    pass_timer::PassTimer,
+    pipeline::PingPongSide,
+    types::SizeSettings,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassSchedule {
+    /// Every simulation step, the ping-pong sides are swapped after each of them
+    Step,
+    /// Once after the steps, on the side holding the trails written last
+    Frame,
+}
+
+pub struct PassContext {
+    pub side: PingPongSide,
+    pub num_agents: u32,
+    pub render_sensors: bool,
+}
+
+/// A compute pass of the simulation, declared by the names of the resources it binds. A resource
+/// both read and written is changed in place: such passes run after the ones only writing it, in
+/// the order they were added, and before the ones only reading it
+pub trait ComputePass: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn schedule(&self) -> PassSchedule {
+        PassSchedule::Step
+    }
+
+    fn inputs(&self) -> &'static [&'static str];
+
+    fn outputs(&self) -> &'static [&'static str];
+
+    fn pipeline_ids(&self) -> Vec<CachedComputePipelineId>;
+
+    fn set_size(&mut self, _size: &SizeSettings) {}
+
+    fn queue_pass_bind_group(
+        &mut self,
+        render_device: &RenderDevice,
+        resources: &PassResources,
+        side: PingPongSide,
+    ) -> Result<(), String>;
+
+    fn execute_pass(
+        &self,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        state: &ComputeSlimeState,
+        context: &PassContext,
+    );
+}
+
+pub type ComputePassConstructor = fn(&mut World) -> Box<dyn ComputePass>;
+
+#[derive(Clone, Default)]
+pub struct ComputePassRegistry {
+    constructors: Vec<ComputePassConstructor>,
+}
+
+pub trait AddComputePass {
+    /// Adds a pass to the simulation. Plugins built after `ComputePlugin` add their passes after
+    /// the built-in ones, so a pass changing the trails in place runs between the agents and the
+    /// diffusion
+    fn add_compute_pass(&mut self, constructor: ComputePassConstructor) -> &mut Self;
+}
+
+impl AddComputePass for App {
+    fn add_compute_pass(&mut self, constructor: ComputePassConstructor) -> &mut Self {
+        let render_app = self.sub_app_mut(RenderApp);
+        render_app
+            .world
+            .get_resource_or_insert_with(ComputePassRegistry::default)
+            .add(constructor);
+
+        self
+    }
+}
+
+impl ComputePassRegistry {
+    pub fn add(&mut self, constructor: ComputePassConstructor) {
+        self.constructors.push(constructor);
+    }
+
+    pub fn build(&self, world: &mut World) -> Vec<Box<dyn ComputePass>> {
+        self.constructors
+            .iter()
+            .map(|constructor| constructor(world))
+            .collect()
+    }
+}
+
+enum PassTexture {
+    Single(TextureView),
+    PingPong([TextureView; 2], PingPongSide),
+}
+
+#[derive(Default)]
+pub struct PassResources {
+    buffers: HashMap<&'static str, Buffer>,
+    textures: HashMap<&'static str, PassTexture>,
+}
+
+impl PassResources {
+    pub fn insert_buffer(&mut self, name: &'static str, buffer: Buffer) {
+        self.buffers.insert(name, buffer);
+    }
+
+    pub fn insert_texture(&mut self, name: &'static str, texture_view: TextureView) {
+        self.textures
+            .insert(name, PassTexture::Single(texture_view));
+    }
+
+    /// `name` is the texture of the current side, `next_name` the one of the other side
+    pub fn insert_ping_pong(
+        &mut self,
+        name: &'static str,
+        next_name: &'static str,
+        texture_views: [TextureView; 2],
+    ) {
+        self.textures
+            .insert(next_name, PassTexture::PingPong(texture_views.clone(), 1));
+        self.textures
+            .insert(name, PassTexture::PingPong(texture_views, 0));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.buffers.contains_key(name) || self.textures.contains_key(name)
+    }
+
+    pub fn buffer(&self, name: &str) -> Result<&Buffer, String> {
+        self.buffers
+            .get(name)
+            .ok_or_else(|| format!("No compute pass buffer named {}", name))
+    }
+
+    pub fn texture(&self, name: &str, side: PingPongSide) -> Result<&TextureView, String> {
+        match self.textures.get(name) {
+            Some(PassTexture::Single(texture_view)) => Ok(texture_view),
+            Some(PassTexture::PingPong(texture_views, offset)) => {
+                Ok(&texture_views[(side + offset) % 2])
+            }
+            None => Err(format!("No compute pass texture named {}", name)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PassGraph {
+    passes: Vec<Box<dyn ComputePass>>,
+    step_order: Vec<usize>,
+    frame_order: Vec<usize>,
+}
+
+impl PassGraph {
+    /// Fails when a pass declares a resource missing from `resource_names`, the names the passes
+    /// are given in `PassResources`, or when the passes depend on each other in a cycle
+    pub fn new(
+        passes: Vec<Box<dyn ComputePass>>,
+        resource_names: &[&str],
+    ) -> Result<Self, PipelineError> {
+        check_resources(&passes, resource_names)?;
+
+        let step_order = sort_passes(&passes, PassSchedule::Step)?;
+        let frame_order = sort_passes(&passes, PassSchedule::Frame)?;
+
+        Ok(PassGraph {
+            passes,
+            step_order,
+            frame_order,
+        })
+    }
+
+    pub fn pipeline_ids(&self) -> Vec<(&'static str, CachedComputePipelineId)> {
+        self.passes
+            .iter()
+            .flat_map(|pass| {
+                pass.pipeline_ids()
+                    .into_iter()
+                    .map(|pipeline_id| (pass.name(), pipeline_id))
+            })
+            .collect()
+    }
+
+    pub fn set_size(&mut self, size: &SizeSettings) {
+        self.passes.iter_mut().for_each(|pass| pass.set_size(size));
+    }
+
+    pub fn queue_bind_groups(
+        &mut self,
+        render_device: &RenderDevice,
+        resources: &PassResources,
+    ) -> Result<(), PipelineError> {
+        for pass in &mut self.passes {
+            for side in 0..2 {
+                pass.queue_pass_bind_group(render_device, resources, side)
+                    .map_err(|message| pass_error(pass.as_ref(), message))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn execute(
+        &self,
+        schedule: PassSchedule,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        state: &ComputeSlimeState,
+        context: &PassContext,
+        timer: &PassTimer,
+    ) {
+        let order = match schedule {
+            PassSchedule::Step => &self.step_order,
+            PassSchedule::Frame => &self.frame_order,
+        };
+
+        for &index in order {
+            let pass = &self.passes[index];
+            timer.start_pass(render_context, pass.name());
+            pass.execute_pass(render_context, pipeline_cache, state, context);
+            timer.end_pass(render_context);
+        }
+    }
+}
+
+fn pass_error(pass: &dyn ComputePass, message: String) -> PipelineError {
+    PipelineError {
+        pass: pass.name(),
+        entry_point: None,
+        message,
+    }
+}
+
+fn check_resources(
+    passes: &[Box<dyn ComputePass>],
+    resource_names: &[&str],
+) -> Result<(), PipelineError> {
+    for pass in passes {
+        if let Some(name) = pass
+            .inputs()
+            .iter()
+            .chain(pass.outputs())
+            .find(|name| !resource_names.contains(name))
+        {
+            return Err(pass_error(
+                pass.as_ref(),
+                format!(
+                    "It binds {}, which is not a simulation buffer or texture",
+                    name
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Access {
+    Write,
+    Modify,
+    Read,
+}
+
+fn access(pass: &dyn ComputePass, name: &str) -> Option<Access> {
+    match (
+        pass.inputs().contains(&name),
+        pass.outputs().contains(&name),
+    ) {
+        (true, true) => Some(Access::Modify),
+        (false, true) => Some(Access::Write),
+        (true, false) => Some(Access::Read),
+        (false, false) => None,
+    }
+}
+
+fn runs_before(first: &dyn ComputePass, second: &dyn ComputePass, added_before: bool) -> bool {
+    first.inputs().iter().chain(first.outputs()).any(|name| {
+        match (access(first, name), access(second, name)) {
+            (Some(Access::Modify), Some(Access::Modify)) => added_before,
+            (Some(first_access), Some(second_access)) => first_access < second_access,
+            _ => false,
+        }
+    })
+}
+
+/// Indices of the passes of `schedule`, each one after the passes it depends on and otherwise in
+/// the order they were added
+fn sort_passes(
+    passes: &[Box<dyn ComputePass>],
+    schedule: PassSchedule,
+) -> Result<Vec<usize>, PipelineError> {
+    let mut remaining: Vec<usize> = (0..passes.len())
+        .filter(|&index| passes[index].schedule() == schedule)
+        .collect();
+    let mut order = Vec::with_capacity(remaining.len());
+
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|&index| {
+            remaining.iter().all(|&other| {
+                other == index
+                    || !runs_before(
+                        passes[other].as_ref(),
+                        passes[index].as_ref(),
+                        other < index,
+                    )
+            })
+        });
+
+        match ready {
+            Some(position) => order.push(remaining.remove(position)),
+            None => {
+                let cycle = find_cycle(passes, &remaining);
+                let names: Vec<_> = cycle.iter().map(|&index| passes[index].name()).collect();
+                return Err(pass_error(
+                    passes[cycle[0]].as_ref(),
+                    format!("Compute passes depend on each other: {}", names.join(", ")),
+                ));
+            }
+        }
+    }
+
+    Ok(order)
+}
+
+fn find_cycle(passes: &[Box<dyn ComputePass>], remaining: &[usize]) -> Vec<usize> {
+    let mut path = vec![remaining[0]];
+
+    loop {
+        let index = path[path.len() - 1];
+        let before = remaining
+            .iter()
+            .copied()
+            .find(|&other| {
+                other != index
+                    && runs_before(
+                        passes[other].as_ref(),
+                        passes[index].as_ref(),
+                        other < index,
+                    )
+            })
+            .unwrap_or(index);
+
+        if let Some(start) = path.iter().position(|&visited| visited == before) {
+            let mut cycle = path.split_off(start);
+            cycle.sort_unstable();
+            return cycle;
+        }
+        path.push(before);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESOURCE_NAMES: [&str; 12] = [
+        "size",
+        "slime_settings",
+        "time",
+        "agents",
+        "species",
+        "interactions",
+        "display",
+        "trail",
+        "diffuse_settings",
+        "blur",
+        "next_display",
+        "next_trail",
+    ];
+
+    struct StubPass {
+        name: &'static str,
+        schedule: PassSchedule,
+        inputs: &'static [&'static str],
+        outputs: &'static [&'static str],
+    }
+
+    impl ComputePass for StubPass {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn schedule(&self) -> PassSchedule {
+            self.schedule
+        }
+
+        fn inputs(&self) -> &'static [&'static str] {
+            self.inputs
+        }
+
+        fn outputs(&self) -> &'static [&'static str] {
+            self.outputs
+        }
+
+        fn pipeline_ids(&self) -> Vec<CachedComputePipelineId> {
+            Vec::new()
+        }
+
+        fn queue_pass_bind_group(
+            &mut self,
+            _render_device: &RenderDevice,
+            _resources: &PassResources,
+            _side: PingPongSide,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn execute_pass(
+            &self,
+            _render_context: &mut RenderContext,
+            _pipeline_cache: &PipelineCache,
+            _state: &ComputeSlimeState,
+            _context: &PassContext,
+        ) {
+        }
+    }
+
+    fn pass(
+        name: &'static str,
+        inputs: &'static [&'static str],
+        outputs: &'static [&'static str],
+    ) -> Box<dyn ComputePass> {
+        Box::new(StubPass {
+            name,
+            schedule: PassSchedule::Step,
+            inputs,
+            outputs,
+        })
+    }
+
+    fn slime() -> Box<dyn ComputePass> {
+        pass(
+            "slime",
+            &[
+                "size",
+                "slime_settings",
+                "time",
+                "agents",
+                "species",
+                "interactions",
+                "display",
+                "trail",
+            ],
+            &["agents", "trail"],
+        )
+    }
+
+    fn diffuse() -> Box<dyn ComputePass> {
+        pass(
+            "diffuse",
+            &["size", "diffuse_settings", "time", "trail"],
+            &["blur", "next_display", "next_trail"],
+        )
+    }
+
+    fn step_names(passes: &[Box<dyn ComputePass>]) -> Vec<&'static str> {
+        sort_passes(passes, PassSchedule::Step)
+            .unwrap()
+            .into_iter()
+            .map(|index| passes[index].name())
+            .collect()
+    }
+
+    #[test]
+    fn writers_run_before_modifiers_before_readers() {
+        let passes = vec![
+            pass("read", &["trail"], &[]),
+            pass("modify", &["trail"], &["trail"]),
+            pass("write", &[], &["trail"]),
+        ];
+        assert_eq!(step_names(&passes), ["write", "modify", "read"]);
+
+        assert!(runs_before(passes[2].as_ref(), passes[1].as_ref(), false));
+        assert!(runs_before(passes[1].as_ref(), passes[0].as_ref(), false));
+        assert!(!runs_before(passes[0].as_ref(), passes[2].as_ref(), true));
+    }
+
+    #[test]
+    fn modifiers_keep_the_order_they_were_added() {
+        let passes = vec![
+            pass("read", &["trail"], &[]),
+            pass("first", &["trail"], &["trail"]),
+            pass("second", &["trail"], &["trail"]),
+            pass("write", &[], &["trail"]),
+        ];
+        assert_eq!(step_names(&passes), ["write", "first", "second", "read"]);
+
+        assert!(runs_before(passes[1].as_ref(), passes[2].as_ref(), true));
+        assert!(!runs_before(passes[2].as_ref(), passes[1].as_ref(), false));
+    }
+
+    #[test]
+    fn added_passes_run_between_slime_and_diffuse() {
+        let passes = vec![pass("trail stats", &["trail"], &[]), slime(), diffuse()];
+        assert_eq!(step_names(&passes), ["slime", "trail stats", "diffuse"]);
+
+        let passes = vec![
+            slime(),
+            diffuse(),
+            pass("obstacles", &["size", "trail"], &["trail"]),
+        ];
+        assert_eq!(step_names(&passes), ["slime", "obstacles", "diffuse"]);
+    }
+
+    #[test]
+    fn frame_passes_are_sorted_apart() {
+        let mut passes = vec![slime(), diffuse()];
+        passes.insert(
+            0,
+            Box::new(StubPass {
+                name: "colour map",
+                schedule: PassSchedule::Frame,
+                inputs: &["display"],
+                outputs: &["render_display"],
+            }),
+        );
+        assert_eq!(step_names(&passes), ["slime", "diffuse"]);
+        assert_eq!(sort_passes(&passes, PassSchedule::Frame).unwrap(), [0]);
+    }
+
+    #[test]
+    fn cycles_name_their_passes() {
+        let passes = vec![
+            pass("reader", &["a"], &[]),
+            pass("first", &["a"], &["b"]),
+            pass("second", &["b"], &["a"]),
+        ];
+
+        let error = sort_passes(&passes, PassSchedule::Step).unwrap_err();
+        assert_eq!(error.pass, "first");
+        assert_eq!(
+            error.message,
+            "Compute passes depend on each other: first, second"
+        );
+    }
+
+    #[test]
+    fn unknown_resources_name_the_pass() {
+        let passes = vec![
+            slime(),
+            diffuse(),
+            pass("obstacles", &["trail"], &["obstacle_map"]),
+        ];
+
+        assert!(check_resources(&passes[..2], &RESOURCE_NAMES).is_ok());
+        let error = check_resources(&passes, &RESOURCE_NAMES).unwrap_err();
+        assert_eq!(error.pass, "obstacles");
+        assert!(error.message.contains("obstacle_map"), "{}", error);
+    }
+}
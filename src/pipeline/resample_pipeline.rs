@@ -0,0 +1,256 @@
+use std::borrow::Cow;
+
+use bevy::{
+    prelude::{AssetServer, Handle, Shader, World},
+    render::{
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+    },
+};
+
+use crate::{
+    compute_render_node::ComputeSlimeState,
+    pipeline::Pipeline,
+    types::{Agent, SizeSettings},
+};
+
+const RESAMPLE_SIZE: f32 = 8.0;
+const PARTICLES_PER_GROUP: f32 = 64.0;
+
+pub struct ResamplePipeline {
+    pub bind_group: Option<BindGroup>,
+    pub bind_group_layout: BindGroupLayout,
+    pub resample_pipeline_id: CachedComputePipelineId,
+    pub scale_agents_pipeline_id: CachedComputePipelineId,
+}
+
+pub struct ResampleBuffers<'a> {
+    pub old_size_buffer: Buffer,
+    pub new_size_buffer: Buffer,
+    pub agents_buffer: Buffer,
+    /// Live agent count, the agents past it are left as they are
+    pub num_agents_buffer: Buffer,
+    pub texture_view_read: &'a TextureView,
+    pub texture_view_write: &'a TextureView,
+    pub trail_texture_view_write: &'a TextureView,
+}
+
+pub struct ResampleExecute {
+    pub new_size: SizeSettings,
+    pub num_agents: u32,
+    pub resample_trails: bool,
+    pub scale_agents: bool,
+}
+
+impl<'a> Pipeline<'a> for ResamplePipeline {
+    type CreationSettings = ();
+    type BindGroupSettings = ResampleBuffers<'a>;
+    type ExecuteSettings = ResampleExecute;
+
+    fn new(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let bind_group_layout =
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<SizeSettings>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<SizeSettings>() as u64
+                            ),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<Agent>() as u64),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::ReadOnly,
+                            format: TextureFormat::R32Float,
+                            view_dimension: TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: TextureFormat::R32Float,
+                            view_dimension: TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<u32>() as u64),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::StorageTexture {
+                            access: StorageTextureAccess::WriteOnly,
+                            format: TextureFormat::R32Float,
+                            view_dimension: TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let asset_server = world.resource::<AssetServer>();
+        let shader: Handle<Shader> = asset_server.load("resample.wgsl");
+
+        let mut pipeline_cache = world.resource_mut::<PipelineCache>();
+        let resample_pipeline_id =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: Some(vec![bind_group_layout.clone()]),
+                shader: shader.clone(),
+                shader_defs: vec![],
+                entry_point: Cow::from("resample_main"),
+            });
+        let scale_agents_pipeline_id =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: Some(vec![bind_group_layout.clone()]),
+                shader,
+                shader_defs: vec![],
+                entry_point: Cow::from("scale_agents_main"),
+            });
+
+        Self {
+            bind_group: None,
+            bind_group_layout,
+            resample_pipeline_id,
+            scale_agents_pipeline_id,
+        }
+    }
+
+    fn queue_bind_group(&mut self, render_device: &RenderDevice, buffers: &ResampleBuffers) {
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffers.old_size_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: buffers.new_size_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: buffers.agents_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::TextureView(buffers.texture_view_read),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(buffers.texture_view_write),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: buffers.num_agents_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(buffers.trail_texture_view_write),
+                },
+            ],
+        });
+
+        self.bind_group = Some(bind_group);
+    }
+
+    fn execute(
+        &self,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        _state: &ComputeSlimeState,
+        execute_settings: &Self::ExecuteSettings,
+    ) {
+        match &self.bind_group {
+            Some(bind_group) => {
+                render_context
+                    .command_encoder
+                    .push_debug_group("Execute resample pipeline");
+                {
+                    let mut pass = render_context
+                        .command_encoder
+                        .begin_compute_pass(&ComputePassDescriptor::default());
+
+                    pass.set_bind_group(0, bind_group, &[]);
+
+                    // a resize before the shaders are compiled starts from blank trails
+                    if execute_settings.resample_trails {
+                        if let Some(resample_pipeline) =
+                            pipeline_cache.get_compute_pipeline(self.resample_pipeline_id)
+                        {
+                            pass.set_pipeline(resample_pipeline);
+                            pass.dispatch_workgroups(
+                                (execute_settings.new_size.width as f32 / RESAMPLE_SIZE).ceil()
+                                    as u32,
+                                (execute_settings.new_size.height as f32 / RESAMPLE_SIZE).ceil()
+                                    as u32,
+                                1,
+                            );
+                        }
+                    }
+
+                    if execute_settings.scale_agents {
+                        if let Some(scale_agents_pipeline) =
+                            pipeline_cache.get_compute_pipeline(self.scale_agents_pipeline_id)
+                        {
+                            pass.set_pipeline(scale_agents_pipeline);
+                            pass.dispatch_workgroups(
+                                (execute_settings.num_agents as f32 / PARTICLES_PER_GROUP).ceil()
+                                    as u32,
+                                1,
+                                1,
+                            );
+                        }
+                    }
+                }
+                render_context.command_encoder.pop_debug_group();
+            }
+            None => panic!("Bind group not set"),
+        }
+    }
+}
@@ -10,41 +10,48 @@ use bevy::{
 
 use crate::{
     compute_render_node::ComputeSlimeState,
-    pipeline::Pipeline,
-    types::{Agent, SizeSettings, SlimeSettings, TimeBuffer},
+    pipeline::{
+        pass_graph::{ComputePass, PassContext, PassResources},
+        PingPongSide, Pipeline,
+    },
+    types::{Agent, SizeSettings, SlimeSettings, SpeciesSettings, TimeBuffer},
 };
 
 const PARTICLES_PER_GROUP: usize = 64;
 
 pub struct SlimeSimPipeline {
-    pub bind_group: Option<BindGroup>,
+    /// One per ping-pong side
+    pub bind_groups: [Option<BindGroup>; 2],
     pub bind_group_layout: BindGroupLayout,
     pub slime_sim_pipeline_id: CachedComputePipelineId,
-    pub workgroup_size: u32,
 }
 
-pub struct SlimeSimSetup {
+/// Live agent count, agents past it in the buffer are left untouched
+pub struct SlimeSimExecute {
     pub num_agents: u32,
+    pub side: PingPongSide,
 }
 
 #[derive(Debug)]
 pub struct SlimeSimBuffers<'a> {
+    pub side: PingPongSide,
     pub texture_view_read: &'a TextureView,
     pub texture_view_write: &'a TextureView,
     pub agents_buffer: Buffer,
     pub settings_buffer: Buffer,
     pub time_buffer: Buffer,
     pub size_buffers: Buffer,
+    pub species_buffer: Buffer,
+    pub interactions_buffer: Buffer,
 }
 
 impl<'a> Pipeline<'a> for SlimeSimPipeline {
-    type CreationSettings = SlimeSimSetup;
+    type CreationSettings = ();
     type BindGroupSettings = SlimeSimBuffers<'a>;
-    type ExecuteSettings = ();
+    type ExecuteSettings = SlimeSimExecute;
 
     fn new(world: &mut World) -> Self {
         let render_device = world.resource::<RenderDevice>();
-        let settings = world.resource::<Self::CreationSettings>();
 
         let bind_group_layout =
             render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -92,10 +99,7 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
                         ty: BindingType::Buffer {
                             ty: BufferBindingType::Storage { read_only: false },
                             has_dynamic_offset: false,
-                            min_binding_size: BufferSize::new(
-                                (std::mem::size_of::<Agent>() * settings.num_agents as usize)
-                                    as u64,
-                            ),
+                            min_binding_size: BufferSize::new(std::mem::size_of::<Agent>() as u64),
                         },
                         count: None,
                     },
@@ -104,8 +108,8 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
                         visibility: ShaderStages::COMPUTE,
                         ty: BindingType::StorageTexture {
                             access: StorageTextureAccess::ReadOnly,
-                            format: TextureFormat::Rgba16Float,
-                            view_dimension: TextureViewDimension::D2,
+                            format: TextureFormat::R32Float,
+                            view_dimension: TextureViewDimension::D2Array,
                         },
                         count: None,
                     },
@@ -114,8 +118,30 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
                         visibility: ShaderStages::COMPUTE,
                         ty: BindingType::StorageTexture {
                             access: StorageTextureAccess::WriteOnly,
-                            format: TextureFormat::Rgba16Float,
-                            view_dimension: TextureViewDimension::D2,
+                            format: TextureFormat::R32Float,
+                            view_dimension: TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(
+                                std::mem::size_of::<SpeciesSettings>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: BufferSize::new(std::mem::size_of::<f32>() as u64),
                         },
                         count: None,
                     },
@@ -125,9 +151,6 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
         let asset_server = world.resource::<AssetServer>();
         let shader: Handle<Shader> = asset_server.load("slime_simulation.wgsl");
 
-        let workgroup_size =
-            ((settings.num_agents as f32) / (PARTICLES_PER_GROUP as f32)).ceil() as u32;
-
         let mut pipeline_cache = world.resource_mut::<PipelineCache>();
         let slime_sim_pipeline_id =
             pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
@@ -139,10 +162,9 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
             });
 
         Self {
-            bind_group: None,
+            bind_groups: [None, None],
             bind_group_layout,
             slime_sim_pipeline_id,
-            workgroup_size,
         }
     }
 
@@ -175,10 +197,18 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
                     binding: 5,
                     resource: BindingResource::TextureView(&buffers.texture_view_write),
                 },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: buffers.species_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: buffers.interactions_buffer.as_entire_binding(),
+                },
             ],
         });
 
-        self.bind_group = Some(bind_group);
+        self.bind_groups[buffers.side] = Some(bind_group);
     }
 
     fn execute(
@@ -186,9 +216,16 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
         render_context: &mut RenderContext,
         pipeline_cache: &PipelineCache,
         _state: &ComputeSlimeState,
-        _execute_settings: &Self::ExecuteSettings,
+        execute_settings: &Self::ExecuteSettings,
     ) {
-        match &self.bind_group {
+        let workgroup_size =
+            ((execute_settings.num_agents as f32) / (PARTICLES_PER_GROUP as f32)).ceil() as u32;
+
+        if workgroup_size == 0 {
+            return;
+        }
+
+        match &self.bind_groups[execute_settings.side] {
             Some(bind_group) => {
                 render_context
                     .command_encoder
@@ -205,7 +242,7 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
                         .unwrap();
 
                     pass.set_pipeline(slime_sim_pipeline);
-                    pass.dispatch_workgroups(self.workgroup_size, 1, 1);
+                    pass.dispatch_workgroups(workgroup_size, 1, 1);
                 }
                 render_context.command_encoder.pop_debug_group();
             }
@@ -213,3 +250,69 @@ impl<'a> Pipeline<'a> for SlimeSimPipeline {
         }
     }
 }
+
+impl ComputePass for SlimeSimPipeline {
+    fn name(&self) -> &'static str {
+        "slime simulation"
+    }
+
+    fn inputs(&self) -> &'static [&'static str] {
+        &[
+            "size",
+            "slime_settings",
+            "time",
+            "agents",
+            "species",
+            "interactions",
+            "display",
+            "trail",
+        ]
+    }
+
+    /// The agents deposit onto the trails, the pixels they miss keep their value
+    fn outputs(&self) -> &'static [&'static str] {
+        &["agents", "trail"]
+    }
+
+    fn pipeline_ids(&self) -> Vec<CachedComputePipelineId> {
+        vec![self.slime_sim_pipeline_id]
+    }
+
+    fn queue_pass_bind_group(
+        &mut self,
+        render_device: &RenderDevice,
+        resources: &PassResources,
+        side: PingPongSide,
+    ) -> Result<(), String> {
+        let buffers = SlimeSimBuffers {
+            side,
+            size_buffers: resources.buffer("size")?.clone(),
+            agents_buffer: resources.buffer("agents")?.clone(),
+            settings_buffer: resources.buffer("slime_settings")?.clone(),
+            time_buffer: resources.buffer("time")?.clone(),
+            species_buffer: resources.buffer("species")?.clone(),
+            interactions_buffer: resources.buffer("interactions")?.clone(),
+            texture_view_read: resources.texture("display", side)?,
+            texture_view_write: resources.texture("trail", side)?,
+        };
+
+        self.queue_bind_group(render_device, &buffers);
+
+        Ok(())
+    }
+
+    fn execute_pass(
+        &self,
+        render_context: &mut RenderContext,
+        pipeline_cache: &PipelineCache,
+        state: &ComputeSlimeState,
+        context: &PassContext,
+    ) {
+        let execute_settings = SlimeSimExecute {
+            num_agents: context.num_agents,
+            side: context.side,
+        };
+
+        self.execute(render_context, pipeline_cache, state, &execute_settings);
+    }
+}
@@ -0,0 +1,63 @@
+use std::{env, fs, path::PathBuf};
+
+const APP_DIRECTORY: &str = "rust-slime-simulation";
+
+/// Presets saved from the GUI live in the user configuration directory
+pub fn user_preset_dir() -> PathBuf {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    config_dir.join(APP_DIRECTORY).join("presets")
+}
+
+/// File a preset named in the GUI is saved to. The name has to be a plain file name, so that the
+/// preset cannot be written outside of the user preset directory
+pub fn user_preset_path(name: &str) -> Result<PathBuf, String> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(String::from("The preset name is empty"));
+    }
+    if name.contains(['/', '\\']) || name.contains("..") {
+        return Err(format!(
+            "Invalid preset name {}, it cannot contain path separators or ..",
+            name
+        ));
+    }
+
+    Ok(user_preset_dir().join(format!("{}.ron", name)))
+}
+
+/// Every RON file of the user preset directory, sorted by name
+pub fn list_user_presets() -> Vec<PathBuf> {
+    let mut presets: Vec<PathBuf> = fs::read_dir(user_preset_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    presets.sort();
+    presets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_names_stay_in_the_preset_directory() {
+        let path = user_preset_path(" Spirals ").unwrap();
+        assert_eq!(path, user_preset_dir().join("Spirals.ron"));
+
+        for name in ["", "../../x", "/tmp/x", "nested/x", "..\\x", "x\\y", ".."] {
+            assert!(user_preset_path(name).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cpu_simulation::TrailMap,
+    image_export::{write_y4m_frame, write_y4m_header, ImageFormat},
+    screenshot::{save_image, timestamp},
+    types::AppSettings,
+};
+
+/// Directory recordings are written to, relative to the working directory
+pub const RECORDING_DIRECTORY: &str = "recordings";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordingFormat {
+    /// Numbered 8 bits PNG files in a directory
+    PngSequence,
+    /// A single uncompressed YUV4MPEG2 video file
+    Y4m,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingSettings {
+    /// Directory of a PNG sequence, file of a video
+    pub path: PathBuf,
+    pub format: RecordingFormat,
+    /// Rendered frames between two captured frames
+    pub every: u32,
+}
+
+impl RecordingSettings {
+    /// A new timestamped recording of the recording directory
+    pub fn timestamped(format: RecordingFormat, every: u32) -> Self {
+        let name = format!("recording_{}", timestamp());
+        let path = match format {
+            RecordingFormat::PngSequence => Path::new(RECORDING_DIRECTORY).join(name),
+            RecordingFormat::Y4m => Path::new(RECORDING_DIRECTORY).join(name + ".y4m"),
+        };
+
+        Self {
+            path,
+            format,
+            every: every.max(1),
+        }
+    }
+}
+
+/// Writes the captured frames of a recording. Every rendered frame runs the same fixed steps, so
+/// captured frames are evenly spaced in simulated time however long the capture takes
+pub struct Recorder {
+    settings: RecordingSettings,
+    rendered_frames: u32,
+    captured_frames: u32,
+    /// Opened with the first frame, along the width and height written in its header
+    video: Option<(BufWriter<File>, u32, u32)>,
+}
+
+impl Recorder {
+    pub fn new(settings: RecordingSettings) -> Self {
+        Self {
+            settings,
+            rendered_frames: 0,
+            captured_frames: 0,
+            video: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.settings.path
+    }
+
+    /// Counts a rendered frame, returns whether it has to be captured
+    pub fn next_frame(&mut self) -> bool {
+        let capture = self.rendered_frames.is_multiple_of(self.settings.every);
+        self.rendered_frames = self.rendered_frames.wrapping_add(1);
+        capture
+    }
+
+    pub fn write_frame(
+        &mut self,
+        image: &TrailMap,
+        app_settings: &AppSettings,
+    ) -> Result<(), String> {
+        match self.settings.format {
+            RecordingFormat::PngSequence => {
+                let path = self
+                    .settings
+                    .path
+                    .join(format!("frame_{:06}.png", self.captured_frames));
+                save_image(&path, ImageFormat::Png8, image, app_settings)?;
+            }
+            RecordingFormat::Y4m => {
+                if self.video.is_none() {
+                    self.video = Some((
+                        self.create_video(image, app_settings)?,
+                        image.width,
+                        image.height,
+                    ));
+                }
+
+                if let Some((writer, width, height)) = &mut self.video {
+                    if (*width, *height) != (image.width, image.height) {
+                        return Err(String::from(
+                            "The simulation was resized, a video keeps its size",
+                        ));
+                    }
+
+                    write_y4m_frame(writer, &image.pixels).map_err(|error| {
+                        format!("Cannot write {}: {}", self.settings.path.display(), error)
+                    })?;
+                }
+            }
+        }
+
+        self.captured_frames += 1;
+        Ok(())
+    }
+
+    /// Flushes the video, the recording is complete afterwards
+    pub fn finish(self) -> Result<u32, String> {
+        if let Some((mut writer, _, _)) = self.video {
+            writer.flush().map_err(|error| {
+                format!("Cannot write {}: {}", self.settings.path.display(), error)
+            })?;
+        }
+
+        Ok(self.captured_frames)
+    }
+
+    fn create_video(
+        &self,
+        image: &TrailMap,
+        app_settings: &AppSettings,
+    ) -> Result<BufWriter<File>, String> {
+        let path = &self.settings.path;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .map_err(|error| format!("Cannot create {}: {}", directory.display(), error))?;
+        }
+
+        let file = File::create(path)
+            .map_err(|error| format!("Cannot create {}: {}", path.display(), error))?;
+        let mut writer = BufWriter::new(file);
+
+        // played back at the simulated speed, each captured frame spans the steps in between
+        let frame_rate = frame_rate(
+            app_settings.time_step,
+            app_settings.substeps.max(1) * self.settings.every,
+        );
+
+        write_y4m_header(&mut writer, image.width, image.height, frame_rate)
+            .map_err(|error| format!("Cannot write {}: {}", path.display(), error))?;
+        Ok(writer)
+    }
+}
+
+/// Frames per second of a video whose frames are `steps_per_frame` steps apart, as the simplest
+/// fraction within the precision of the time step, so a step of 1/60 plays at 60/1
+fn frame_rate(time_step: f32, steps_per_frame: u32) -> (u32, u32) {
+    let frames_per_second = 1.0 / (time_step as f64 * steps_per_frame as f64);
+    if !frames_per_second.is_finite() || frames_per_second <= 0.0 {
+        return (1, 1);
+    }
+    let tolerance = frames_per_second * f32::EPSILON as f64;
+
+    // convergents of the continued fraction, until one is close enough or too large for the header
+    let (mut numerator, mut previous_numerator) = (1_u64, 0_u64);
+    let (mut denominator, mut previous_denominator) = (0_u64, 1_u64);
+    let mut remainder = frames_per_second;
+    loop {
+        let term = remainder.floor();
+        let next_numerator = term as u64 * numerator + previous_numerator;
+        let next_denominator = term as u64 * denominator + previous_denominator;
+        if next_numerator > u32::MAX as u64 || next_denominator > u32::MAX as u64 {
+            break;
+        }
+        (previous_numerator, numerator) = (numerator, next_numerator);
+        (previous_denominator, denominator) = (denominator, next_denominator);
+
+        let error = (numerator as f64 / denominator as f64 - frames_per_second).abs();
+        if error <= tolerance || remainder == term {
+            break;
+        }
+        remainder = 1.0 / (remainder - term);
+    }
+
+    (numerator.max(1) as u32, denominator.max(1) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_nth_frame_is_captured() {
+        let mut recorder = Recorder::new(RecordingSettings::timestamped(
+            RecordingFormat::PngSequence,
+            3,
+        ));
+        let captured: Vec<u32> = (0..8).filter(|_| recorder.next_frame()).collect();
+        assert_eq!(captured, [0, 3, 6]);
+
+        let mut recorder = Recorder::new(RecordingSettings::timestamped(RecordingFormat::Y4m, 0));
+        assert!((0..4).all(|_| recorder.next_frame()));
+    }
+
+    #[test]
+    fn frame_rate_is_the_simplest_fraction() {
+        assert_eq!(frame_rate(0.016, 1), (125, 2));
+        assert_eq!(frame_rate(0.02, 3), (50, 3));
+        assert_eq!(frame_rate(1.5, 2), (1, 3));
+        assert_eq!(frame_rate(1.0 / 60.0, 1), (60, 1));
+        assert_eq!(frame_rate(1.0 / 60.0, 4), (15, 1));
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ron::ser::PrettyConfig;
+
+use crate::{
+    cpu_simulation::TrailMap,
+    image_export::{write_image, ImageFormat},
+    types::AppSettings,
+};
+
+/// Directory screenshots are written to, relative to the working directory
+pub const SCREENSHOT_DIRECTORY: &str = "screenshots";
+
+#[derive(Clone, Debug)]
+pub struct ScreenshotRequest {
+    pub path: PathBuf,
+    pub format: ImageFormat,
+}
+
+impl ScreenshotRequest {
+    /// A new timestamped file of the screenshot directory
+    pub fn timestamped(format: ImageFormat) -> Self {
+        let path = Path::new(SCREENSHOT_DIRECTORY).join(format!(
+            "screenshot_{}.{}",
+            timestamp(),
+            format.extension()
+        ));
+
+        Self { path, format }
+    }
+}
+
+/// Writes the displayed image, the active settings are stored in the file as RON text
+pub fn save_image(
+    path: &Path,
+    format: ImageFormat,
+    image: &TrailMap,
+    app_settings: &AppSettings,
+) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)
+            .map_err(|error| format!("Cannot create {}: {}", directory.display(), error))?;
+    }
+
+    let settings = ron::ser::to_string_pretty(app_settings, PrettyConfig::default())
+        .map_err(|error| format!("Cannot serialize settings: {}", error))?;
+
+    write_image(
+        path,
+        format,
+        image.width,
+        image.height,
+        &image.pixels,
+        &[
+            ("Software", env!("CARGO_PKG_NAME")),
+            ("Settings", &settings),
+        ],
+    )
+}
+
+/// UTC date and time down to the millisecond, sorts like the files were taken
+pub fn timestamp() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}-{:03}",
+        year,
+        month,
+        day,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Gregorian date of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_from_days_counts_from_the_epoch() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(20088), (2024, 12, 31));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
@@ -0,0 +1,134 @@
+use std::{fs, path::PathBuf, time::SystemTime};
+
+use bevy::prelude::{App, Plugin, Res, ResMut, Time};
+
+use crate::{
+    types::{AppPreset, AppSettings},
+    AppSettingsUpdated, AppShouldReset,
+};
+
+/// Seconds between two checks of the settings file modification time
+const POLL_INTERVAL: f64 = 0.5;
+
+pub struct SettingsWatcherPlugin;
+
+impl Plugin for SettingsWatcherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SettingsWatcher>()
+            .add_system(watch_settings_file);
+    }
+}
+
+/// Follows the active preset file and reloads it when it is written
+#[derive(Default)]
+pub struct SettingsWatcher {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    next_check: f64,
+    /// Why the last reload failed, cleared by the next successful one
+    pub error: Option<String>,
+}
+
+fn watch_settings_file(
+    time: Res<Time>,
+    mut settings_watcher: ResMut<SettingsWatcher>,
+    mut app_settings: ResMut<AppSettings>,
+    mut app_should_reset: ResMut<AppShouldReset>,
+    mut app_settings_updated: ResMut<AppSettingsUpdated>,
+) {
+    let now = time.seconds_since_startup();
+    if now < settings_watcher.next_check {
+        return;
+    }
+    settings_watcher.next_check = now + POLL_INTERVAL;
+
+    let path = match &app_settings.app_preset {
+        AppPreset::File(path) => path.clone(),
+        _ => {
+            settings_watcher.path = None;
+            return;
+        }
+    };
+
+    let modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+
+    // another preset became active, its current content is already applied
+    if settings_watcher.path.as_ref() != Some(&path) {
+        settings_watcher.path = Some(path);
+        settings_watcher.modified = modified;
+        settings_watcher.error = None;
+        return;
+    }
+
+    if modified == settings_watcher.modified {
+        return;
+    }
+    settings_watcher.modified = modified;
+
+    match AppSettings::load(&path) {
+        Ok(reloaded) => {
+            settings_watcher.error = None;
+
+            if apply_reloaded_settings(&mut app_settings, reloaded) {
+                app_should_reset.0 = true;
+            } else {
+                app_settings_updated.0 = true;
+            }
+        }
+        Err(error) => settings_watcher.error = Some(error),
+    }
+}
+
+/// Replaces the settings, returns whether the agents have to be respawned for them to apply
+fn apply_reloaded_settings(app_settings: &mut AppSettings, mut reloaded: AppSettings) -> bool {
+    // the window keeps driving the size
+    if reloaded.fit_to_window {
+        reloaded.width = app_settings.width;
+        reloaded.height = app_settings.height;
+    }
+    reloaded.app_preset = app_settings.app_preset.clone();
+
+    // the diffusion kernel is a fixed size uniform, any radius applies without a reset
+    let needs_reset = reloaded.num_agents != app_settings.num_agents
+        || reloaded.seed != app_settings.seed
+        || reloaded.agent_distribution != app_settings.agent_distribution
+        || reloaded.species_assignment != app_settings.species_assignment
+        || reloaded.num_species() != app_settings.num_species()
+        || reloaded.width != app_settings.width
+        || reloaded.height != app_settings.height;
+
+    *app_settings = reloaded;
+    needs_reset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DiffusionKernel;
+
+    #[test]
+    fn reloads_reset_only_for_new_agents() {
+        let mut app_settings = AppSettings {
+            app_preset: AppPreset::File(PathBuf::from("tuned.ron")),
+            ..AppSettings::default()
+        };
+
+        let reloaded = AppSettings {
+            trail_weight: 2.0,
+            diffusion_kernel: DiffusionKernel::Box { radius: 5 },
+            ..app_settings.clone()
+        };
+        assert!(!apply_reloaded_settings(&mut app_settings, reloaded));
+        assert_eq!(app_settings.trail_weight, 2.0);
+
+        let reloaded = AppSettings {
+            seed: app_settings.seed + 1,
+            app_preset: AppPreset::Default,
+            ..app_settings.clone()
+        };
+        assert!(apply_reloaded_settings(&mut app_settings, reloaded));
+        assert!(matches!(app_settings.app_preset, AppPreset::File(_)));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{fs, path::Path};
+
+use ron::ser::PrettyConfig;
+
+use crate::{
+    compute_slime_pipeline::MAX_AGENTS,
+    types::{Agent, AppSettings},
+};
+
+const MAGIC: &[u8; 8] = b"SLIMESNP";
+const VERSION: u32 = 2;
+
+/// Bytes of a R32Float texel of the trail layers
+pub const TEXEL_SIZE: usize = 4;
+
+/// Complete simulation state, enough to continue a run from the moment it was taken.
+///
+/// The file is the magic and version, the settings as RON text, the clock, then the agents buffer
+/// and the display texture as raw little endian GPU data, every length prefixed by a `u32`
+pub struct Snapshot {
+    /// Size and agent count match the agents and display below
+    pub app_settings: AppSettings,
+    pub time: f32,
+    pub frame: u32,
+    pub agents: Vec<Agent>,
+    /// Trails of the display texture, one layer of R32Float texels per species
+    pub display: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let bytes = self.to_bytes()?;
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .map_err(|error| format!("Cannot create {}: {}", directory.display(), error))?;
+        }
+
+        fs::write(path, bytes)
+            .map_err(|error| format!("Cannot write {}: {}", path.display(), error))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|error| format!("Cannot read {}: {}", path.display(), error))?;
+
+        Self::from_bytes(&bytes)
+            .map_err(|reason| format!("Invalid snapshot {}: {}", path.display(), reason))
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let settings = ron::ser::to_string_pretty(&self.app_settings, PrettyConfig::default())
+            .map_err(|error| format!("Cannot serialize settings: {}", error))?;
+
+        let mut bytes = Vec::with_capacity(
+            64 + settings.len()
+                + self.agents.len() * std::mem::size_of::<Agent>()
+                + self.display.len(),
+        );
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        write_block(&mut bytes, settings.as_bytes());
+        bytes.extend_from_slice(&self.time.to_le_bytes());
+        bytes.extend_from_slice(&self.frame.to_le_bytes());
+        write_block(&mut bytes, bytemuck::cast_slice(&self.agents));
+        write_block(&mut bytes, &self.display);
+
+        Ok(bytes)
+    }
+
+    /// Checks everything the GPU upload relies on, a snapshot that loads can be restored
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let truncated = || String::from("truncated file");
+        let mut reader = Reader { bytes };
+
+        if reader.take(MAGIC.len()) != Some(&MAGIC[..]) {
+            return Err(String::from("not a snapshot file"));
+        }
+        match reader.u32() {
+            Some(VERSION) => {}
+            Some(version) => return Err(format!("unsupported version {}", version)),
+            None => return Err(truncated()),
+        }
+
+        let settings = reader.block().ok_or_else(truncated)?;
+        let time = reader.f32().ok_or_else(truncated)?;
+        let frame = reader.u32().ok_or_else(truncated)?;
+        let agents = reader.block().ok_or_else(truncated)?;
+        let display = reader.block().ok_or_else(truncated)?;
+        if !reader.bytes.is_empty() {
+            return Err(String::from("unexpected data after the display"));
+        }
+
+        let settings =
+            std::str::from_utf8(settings).map_err(|_| String::from("settings are not text"))?;
+        let mut app_settings: AppSettings =
+            ron::from_str(settings).map_err(|error| error.to_string())?;
+
+        let agent_size = std::mem::size_of::<Agent>();
+        if agents.len() % agent_size != 0 {
+            return Err(String::from("agents are not whole"));
+        }
+        let agents: Vec<Agent> = agents
+            .chunks_exact(agent_size)
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+        if agents.is_empty() || agents.len() > MAX_AGENTS as usize {
+            return Err(String::from("agent count out of range"));
+        }
+        let num_species = app_settings.num_species() as u32;
+        if agents
+            .iter()
+            .any(|agent| agent.species_index >= num_species)
+        {
+            return Err(String::from("agents of a species that does not exist"));
+        }
+
+        let texels = app_settings.width as usize * app_settings.height as usize;
+        if texels == 0 || display.len() != texels * app_settings.num_species() * TEXEL_SIZE {
+            return Err(String::from(
+                "display does not match the simulation size and species",
+            ));
+        }
+
+        app_settings.num_agents = agents.len() as u32;
+        // the snapshot size wins over the window size, or the trails would be resampled right away
+        app_settings.fit_to_window = false;
+
+        Ok(Self {
+            app_settings,
+            time,
+            frame,
+            agents,
+            display: display.to_vec(),
+        })
+    }
+}
+
+fn write_block(bytes: &mut Vec<u8>, block: &[u8]) {
+    bytes.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(block);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.bytes.len() {
+            return None;
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn block(&mut self) -> Option<&'a [u8]> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut app_settings = AppSettings {
+            width: 4,
+            height: 3,
+            seed: 42,
+            ..AppSettings::default()
+        };
+        app_settings.species_settings[1].move_speed = 12.5;
+        let texels = 4 * 3 * app_settings.num_species();
+
+        Snapshot {
+            app_settings,
+            time: 1.5,
+            frame: 90,
+            agents: vec![
+                Agent {
+                    position: [0.5, 1.25],
+                    angle: 3.0,
+                    species_index: 0,
+                },
+                Agent {
+                    position: [3.5, 2.0],
+                    angle: -1.0,
+                    species_index: 1,
+                },
+            ],
+            display: (0..texels * TEXEL_SIZE).map(|byte| byte as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trips() {
+        let saved = snapshot();
+        let loaded = Snapshot::from_bytes(&saved.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(loaded.time, 1.5);
+        assert_eq!(loaded.frame, 90);
+        assert_eq!(
+            bytemuck::cast_slice::<Agent, u8>(&loaded.agents),
+            bytemuck::cast_slice::<Agent, u8>(&saved.agents)
+        );
+        assert_eq!(loaded.display, saved.display);
+
+        assert_eq!(loaded.app_settings.width, 4);
+        assert_eq!(loaded.app_settings.height, 3);
+        assert_eq!(loaded.app_settings.seed, 42);
+        assert_eq!(loaded.app_settings.num_agents, 2);
+        assert_eq!(loaded.app_settings.species_settings[1].move_speed, 12.5);
+    }
+
+    #[test]
+    fn truncated_snapshots_are_rejected() {
+        let bytes = snapshot().to_bytes().unwrap();
+
+        for length in [0, 4, MAGIC.len() + 4, bytes.len() / 2, bytes.len() - 1] {
+            assert!(
+                Snapshot::from_bytes(&bytes[..length]).is_err(),
+                "{}",
+                length
+            );
+        }
+
+        let mut longer = bytes;
+        longer.push(0);
+        assert!(Snapshot::from_bytes(&longer).is_err());
+    }
+
+    #[test]
+    fn mismatched_snapshots_are_rejected() {
+        let mut short_display = snapshot();
+        short_display.display.truncate(TEXEL_SIZE);
+        let error = Snapshot::from_bytes(&short_display.to_bytes().unwrap())
+            .err()
+            .unwrap();
+        assert!(error.contains("simulation size"));
+
+        // one trail layer per species
+        let mut fewer_species = snapshot();
+        fewer_species.app_settings.remove_species(3);
+        let error = Snapshot::from_bytes(&fewer_species.to_bytes().unwrap())
+            .err()
+            .unwrap();
+        assert!(error.contains("species"));
+
+        let mut unknown_species = snapshot();
+        unknown_species.agents[0].species_index = 9;
+        assert!(Snapshot::from_bytes(&unknown_species.to_bytes().unwrap()).is_err());
+
+        let mut no_agents = snapshot();
+        no_agents.agents.clear();
+        assert!(Snapshot::from_bytes(&no_agents.to_bytes().unwrap()).is_err());
+    }
+}
@@ -1,6 +1,9 @@
 mod agents;
 mod app_settings;
+mod color_map_settings;
 mod diffuse_settings;
+mod diffusion_kernel;
+mod palette;
 mod size_settings;
 mod slime_settings;
 mod species_settings;
@@ -8,8 +11,11 @@ mod time_buffer;
 
 pub use self::{
     agents::Agent,
-    app_settings::{AgentDistribution, AppPreset, AppSettings},
-    diffuse_settings::DiffuseSettings,
+    app_settings::{AgentDistribution, AppPreset, AppSettings, BoundaryMode, SpeciesAssignment},
+    color_map_settings::ColorMapSettings,
+    diffuse_settings::{DiffuseSettings, MAX_KERNEL_WEIGHTS},
+    diffusion_kernel::{DiffusionKernel, MAX_KERNEL_RADIUS, MAX_MATRIX_RADIUS},
+    palette::{Gradient, Palette, GRADIENT_SIZE},
     size_settings::SizeSettings,
     slime_settings::SlimeSettings,
     species_settings::SpeciesSettings,
@@ -1,45 +1,109 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
 use bevy::render::extract_resource::ExtractResource;
+use ron::ser::PrettyConfig;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::SIZE;
 
-use super::SpeciesSettings;
+use super::{DiffusionKernel, Palette, SpeciesSettings};
+
+const DEFAULT_NUM_SPECIES: usize = 4;
 
-#[derive(Clone, Copy, ExtractResource, Debug)]
+const SPECIES_PALETTE: [[f32; 3]; 4] = [
+    [1.0, 1.0, 1.0],
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+];
+
+#[derive(Clone, ExtractResource, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppSettings {
+    #[serde(skip)]
     pub app_preset: AppPreset,
     pub agent_distribution: AgentDistribution,
+    pub species_assignment: SpeciesAssignment,
+    pub boundary_mode: BoundaryMode,
 
     pub width: u32,
     pub height: u32,
+    /// Drives `width` and `height` from the window size
+    pub fit_to_window: bool,
+    /// Stretches the current trails into the new textures when the simulation is resized
+    pub resample_on_resize: bool,
+    /// Keeps agents at the same relative place when the simulation is resized
+    pub scale_agents_on_resize: bool,
     pub num_agents: u32,
+    /// Drives agent spawning and the shader random numbers, together with a fixed time step the
+    /// same seed replays the same run, as long as two species never deposit on the same pixel in
+    /// the same step
+    pub seed: u64,
 
     pub trail_weight: f32,
     pub decay_rate: f32,
     pub diffuse_rate: f32,
+    pub diffusion_kernel: DiffusionKernel,
+
+    /// Simulated seconds of a single step, independent of the frame rate
+    pub time_step: f32,
+    /// Simulation steps run for every rendered frame
+    pub substeps: u32,
+    /// Multiplies the steps run every frame, up to `MAX_SUBSTEPS`, the step itself stays
+    /// `time_step` so a faster run grows the same patterns
+    pub time_scale: f32,
 
     pub render_sensors: bool,
 
-    pub species_settings: [SpeciesSettings; 4],
+    pub species_settings: Vec<SpeciesSettings>,
+    pub species_population: Vec<f32>,
+    /// Row `i` weights how species `i` reacts to each species trail, positive attracts
+    pub species_interactions: Vec<Vec<f32>>,
+    pub species_colors: Vec<[f32; 3]>,
+    /// Colours of the displayed trails, the simulation itself only sees the intensities
+    pub palette: Palette,
+    /// Summed trail intensities mapped to the ends of a gradient palette
+    pub palette_range: [f32; 2],
+    /// Applied to the intensity once mapped, below 1 brings out the faint trails
+    pub palette_gamma: f32,
 }
 
 impl AppSettings {
-    pub fn apply_preset(&mut self) {
-        match self.app_preset {
+    pub fn apply_preset(&mut self) -> Result<(), String> {
+        match &self.app_preset {
             AppPreset::Default => {
                 self.agent_distribution = AgentDistribution::InnerCircle;
+                self.species_assignment = SpeciesAssignment::Interleaved;
+                self.species_population = vec![1.0, 0.0, 0.0, 0.0];
+                self.species_colors = default_species_colors(DEFAULT_NUM_SPECIES);
+                self.reset_palette();
 
-                self.reset_settings();
+                self.reset_settings()
             }
             AppPreset::SuperNova => {
                 self.agent_distribution = AgentDistribution::InnerCircle;
+                self.species_assignment = SpeciesAssignment::Interleaved;
+                self.species_population = vec![1.0, 0.0, 0.0, 0.0];
+                self.species_colors = default_species_colors(DEFAULT_NUM_SPECIES);
+                self.reset_palette();
 
-                self.reset_settings();
+                self.reset_settings()
+            }
+            AppPreset::File(path) => {
+                *self = Self::load(path)?;
+                Ok(())
             }
         }
     }
 
-    pub fn reset_settings(&mut self) {
-        match self.app_preset {
+    pub fn reset_settings(&mut self) -> Result<(), String> {
+        // the species count is chosen by the preset population, settings follow it
+        let num_species = self.species_population.len();
+
+        match &self.app_preset {
             AppPreset::Default => {
                 self.width = SIZE.0;
                 self.height = SIZE.1;
@@ -48,10 +112,16 @@ impl AppSettings {
                 self.trail_weight = 1.0;
                 self.decay_rate = 0.75;
                 self.diffuse_rate = 5.0;
+                self.diffusion_kernel = DiffusionKernel::default();
+
+                self.time_step = 1.0 / 60.0;
+                self.substeps = 1;
+                self.time_scale = 1.0;
 
                 self.render_sensors = false;
 
-                self.species_settings = [SpeciesSettings::default(); 4];
+                self.species_settings = vec![SpeciesSettings::default(); num_species];
+                self.species_interactions = default_species_interactions(num_species);
             }
             AppPreset::SuperNova => {
                 self.width = SIZE.0;
@@ -61,16 +131,279 @@ impl AppSettings {
                 self.trail_weight = 1.0;
                 self.decay_rate = 0.75;
                 self.diffuse_rate = 5.0;
+                self.diffusion_kernel = DiffusionKernel::default();
+
+                self.time_step = 1.0 / 60.0;
+                self.substeps = 1;
+                self.time_scale = 1.0;
 
                 self.render_sensors = false;
 
-                self.species_settings = [SpeciesSettings {
-                    turn_speed: 1.0,
-                    ..Default::default()
-                }; 4];
+                self.species_settings = vec![
+                    SpeciesSettings {
+                        turn_speed: 1.0,
+                        ..Default::default()
+                    };
+                    num_species
+                ];
+                self.species_interactions = default_species_interactions(num_species);
+            }
+            AppPreset::File(path) => {
+                let preset = Self::load(path)?;
+
+                self.width = preset.width;
+                self.height = preset.height;
+                self.num_agents = preset.num_agents;
+
+                self.trail_weight = preset.trail_weight;
+                self.decay_rate = preset.decay_rate;
+                self.diffuse_rate = preset.diffuse_rate;
+                self.diffusion_kernel = preset.diffusion_kernel.clone();
+
+                self.time_step = preset.time_step;
+                self.substeps = preset.substeps;
+                self.time_scale = preset.time_scale;
+
+                self.render_sensors = preset.render_sensors;
+
+                // the file decides the species count, population and colours follow it if needed
+                if preset.num_species() != num_species {
+                    self.species_population = preset.species_population;
+                    self.species_colors = preset.species_colors;
+                }
+                self.species_settings = preset.species_settings;
+                self.species_interactions = preset.species_interactions;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The built-in presets show the species colours
+    fn reset_palette(&mut self) {
+        self.palette = Palette::Species;
+        self.palette_range = [0.0, 1.0];
+        self.palette_gamma = 1.0;
+    }
+
+    pub fn num_species(&self) -> usize {
+        self.species_settings.len()
+    }
+
+    pub fn add_species(&mut self) {
+        let num_species = self.num_species();
+
+        for interactions in self.species_interactions.iter_mut() {
+            interactions.push(-1.0);
+        }
+        let mut interactions = vec![-1.0; num_species + 1];
+        interactions[num_species] = 1.0;
+        self.species_interactions.push(interactions);
+
+        self.species_settings.push(SpeciesSettings::default());
+        self.species_population.push(1.0);
+        self.species_colors.push(species_color(num_species));
+    }
+
+    pub fn remove_species(&mut self, species_index: usize) {
+        if self.num_species() <= 1 || species_index >= self.num_species() {
+            return;
+        }
+
+        self.species_settings.remove(species_index);
+        self.species_population.remove(species_index);
+        self.species_colors.remove(species_index);
+        self.species_interactions.remove(species_index);
+        for interactions in self.species_interactions.iter_mut() {
+            interactions.remove(species_index);
+        }
+    }
+
+    /// Reads a RON settings file, fields missing from the file keep their default value
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("Cannot read {}: {}", path.display(), error))?;
+        let mut app_settings: Self = ron::from_str(&contents)
+            .map_err(|error| format!("Cannot parse {}: {}", path.display(), error))?;
+
+        app_settings.app_preset = AppPreset::File(path.to_path_buf());
+        app_settings.match_species_count();
+        Ok(app_settings)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(|error| format!("Cannot serialize settings: {}", error))?;
+
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .map_err(|error| format!("Cannot create {}: {}", directory.display(), error))?;
+        }
+
+        fs::write(path, contents)
+            .map_err(|error| format!("Cannot write {}: {}", path.display(), error))
+    }
+
+    /// Sets a single field from a RON value, `path` is a field name, indexed by species for the
+    /// species lists like `species_population[1]`, `species[1].turn_speed` (or
+    /// `species_settings[1].turn_speed`) or `species_interactions[0][1]`
+    pub fn set_field(&mut self, path: &str, value: &str) -> Result<(), String> {
+        // a rejected value leaves the settings as they were
+        let mut app_settings = self.clone();
+        app_settings.assign_field(path, value)?;
+
+        // a whole species list may have been replaced
+        app_settings.match_species_count();
+        *self = app_settings;
+        Ok(())
+    }
+
+    fn assign_field(&mut self, path: &str, value: &str) -> Result<(), String> {
+        let (name, indices, field) = parse_field_path(path)?;
+
+        let num_species = self.num_species();
+        let species_index = |index: usize| {
+            if index < num_species {
+                Ok(index)
+            } else {
+                Err(format!(
+                    "Species {} does not exist, there are {} species",
+                    index, num_species
+                ))
+            }
+        };
+
+        match (name, indices.as_slice(), field) {
+            ("species" | "species_settings", [index], Some(field)) => {
+                self.species_settings[species_index(*index)?].set_field(field, value)?
+            }
+            ("species_population", [index], None) => {
+                self.species_population[species_index(*index)?] = parse_value(value)?
+            }
+            ("species_colors", [index], None) => {
+                self.species_colors[species_index(*index)?] = parse_value(value)?
+            }
+            ("species_interactions", [row], None) => {
+                self.species_interactions[species_index(*row)?] = parse_value(value)?
+            }
+            ("species_interactions", [row, column], None) => {
+                self.species_interactions[species_index(*row)?][species_index(*column)?] =
+                    parse_value(value)?
+            }
+            (name, [], None) => match name {
+                "agent_distribution" => self.agent_distribution = parse_value(value)?,
+                "species_assignment" => self.species_assignment = parse_value(value)?,
+                "boundary_mode" => self.boundary_mode = parse_value(value)?,
+                "width" => self.width = parse_value(value)?,
+                "height" => self.height = parse_value(value)?,
+                "fit_to_window" => self.fit_to_window = parse_value(value)?,
+                "resample_on_resize" => self.resample_on_resize = parse_value(value)?,
+                "scale_agents_on_resize" => self.scale_agents_on_resize = parse_value(value)?,
+                "num_agents" => self.num_agents = parse_value(value)?,
+                "seed" => self.seed = parse_value(value)?,
+                "trail_weight" => self.trail_weight = parse_value(value)?,
+                "decay_rate" => self.decay_rate = parse_value(value)?,
+                "diffuse_rate" => self.diffuse_rate = parse_value(value)?,
+                "diffusion_kernel" => self.diffusion_kernel = parse_value(value)?,
+                "time_step" => self.time_step = parse_value(value)?,
+                "substeps" => self.substeps = parse_value(value)?,
+                "time_scale" => self.time_scale = parse_value(value)?,
+                "render_sensors" => self.render_sensors = parse_value(value)?,
+                "species" | "species_settings" => self.species_settings = parse_value(value)?,
+                "species_population" => self.species_population = parse_value(value)?,
+                "species_interactions" => self.species_interactions = parse_value(value)?,
+                "species_colors" => self.species_colors = parse_value(value)?,
+                "palette" => self.palette = parse_value(value)?,
+                "palette_range" => self.palette_range = parse_value(value)?,
+                "palette_gamma" => self.palette_gamma = parse_value(value)?,
+                _ => return Err(format!("Unknown setting {}", name)),
+            },
+            _ => return Err(format!("Invalid setting path {}", path)),
+        }
+
+        Ok(())
+    }
+
+    /// Pads or truncates the species lists of a hand written file to `species_settings`
+    fn match_species_count(&mut self) {
+        if self.species_settings.is_empty() {
+            self.species_settings.push(SpeciesSettings::default());
+        }
+        let num_species = self.num_species();
+
+        self.species_population.resize(num_species, 1.0);
+
+        let default_colors = default_species_colors(num_species);
+        self.species_colors.truncate(num_species);
+        let num_colors = self.species_colors.len();
+        self.species_colors
+            .extend_from_slice(&default_colors[num_colors..]);
+
+        let mut species_interactions = default_species_interactions(num_species);
+        for (row, interactions) in species_interactions
+            .iter_mut()
+            .zip(&self.species_interactions)
+        {
+            for (interaction, value) in row.iter_mut().zip(interactions) {
+                *interaction = *value;
             }
         }
+        self.species_interactions = species_interactions;
+    }
+}
+
+/// Splits `name[i][j].field` into its name, indices and optional field
+fn parse_field_path(path: &str) -> Result<(&str, Vec<usize>, Option<&str>), String> {
+    let invalid = || format!("Invalid setting path {}", path);
+
+    let (head, field) = match path.split_once('.') {
+        Some((head, field)) => (head, Some(field.trim())),
+        None => (path, None),
+    };
+    let (name, mut rest) = head.split_at(head.find('[').unwrap_or(head.len()));
+
+    let mut indices = Vec::new();
+    while !rest.is_empty() {
+        let (index, tail) = rest
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .ok_or_else(invalid)?;
+        indices.push(index.trim().parse().map_err(|_| invalid())?);
+        rest = tail;
+    }
+
+    Ok((name.trim(), indices, field))
+}
+
+pub(super) fn parse_value<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    ron::from_str(value).map_err(|error| format!("Cannot parse {}: {}", value, error))
+}
+
+fn default_species_interactions(num_species: usize) -> Vec<Vec<f32>> {
+    (0..num_species)
+        .map(|i| {
+            (0..num_species)
+                .map(|j| if i == j { 1.0 } else { -1.0 })
+                .collect()
+        })
+        .collect()
+}
+
+fn default_species_colors(num_species: usize) -> Vec<[f32; 3]> {
+    (0..num_species).map(species_color).collect()
+}
+
+/// Golden angle steps keep the hues of consecutive species apart however many there are
+fn species_color(species_index: usize) -> [f32; 3] {
+    if let Some(color) = SPECIES_PALETTE.get(species_index) {
+        return *color;
     }
+
+    let hue = ((species_index - SPECIES_PALETTE.len()) as f32 * 0.381_966 + 1.0 / 6.0).fract();
+    [0.0, 2.0 / 3.0, 1.0 / 3.0].map(|offset| {
+        let distance = ((hue + offset).fract() * 6.0 - 3.0).abs();
+        (distance - 1.0).clamp(0.0, 1.0)
+    })
 }
 
 impl Default for AppSettings {
@@ -78,32 +411,172 @@ impl Default for AppSettings {
         Self {
             app_preset: AppPreset::Default,
             agent_distribution: AgentDistribution::InnerCircle,
+            species_assignment: SpeciesAssignment::Interleaved,
+            boundary_mode: BoundaryMode::Clamp,
 
             width: SIZE.0,
             height: SIZE.1,
+            fit_to_window: true,
+            resample_on_resize: true,
+            scale_agents_on_resize: true,
             num_agents: 100000,
+            seed: 0,
 
             trail_weight: 1.0,
             decay_rate: 0.75,
             diffuse_rate: 5.0,
+            diffusion_kernel: DiffusionKernel::default(),
+
+            time_step: 1.0 / 60.0,
+            substeps: 1,
+            time_scale: 1.0,
 
             render_sensors: false,
 
-            species_settings: [SpeciesSettings::default(); 4],
+            species_settings: vec![SpeciesSettings::default(); DEFAULT_NUM_SPECIES],
+            species_population: vec![1.0, 0.0, 0.0, 0.0],
+            species_interactions: default_species_interactions(DEFAULT_NUM_SPECIES),
+            species_colors: default_species_colors(DEFAULT_NUM_SPECIES),
+            palette: Palette::Species,
+            palette_range: [0.0, 1.0],
+            palette_gamma: 1.0,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum AppPreset {
     Default,
     SuperNova,
+    File(PathBuf),
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+impl AppPreset {
+    pub fn name(&self) -> String {
+        match self {
+            AppPreset::File(path) => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+            _ => format!("{:?}", self),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum AgentDistribution {
     InnerCircle,
     OuterCircle,
     InnerDisk,
     Random,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SpeciesAssignment {
+    Interleaved,
+    Sectored,
+    Random,
+}
+
+/// What agents do when they leave the texture, sensing and diffusion follow the same topology
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    Wrap = 0,
+    Reflect = 1,
+    Clamp = 2,
+    Respawn = 3,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_field_reaches_species_fields() {
+        let mut app_settings = AppSettings::default();
+
+        app_settings
+            .set_field("species_settings[1].move_speed", "12.5")
+            .unwrap();
+        app_settings
+            .set_field("species[2].sensor_size", "3")
+            .unwrap();
+        app_settings
+            .set_field("species_interactions[0][1]", "0.5")
+            .unwrap();
+
+        assert_eq!(app_settings.species_settings[1].move_speed, 12.5);
+        assert_eq!(app_settings.species_settings[0].move_speed, 50.0);
+        assert_eq!(app_settings.species_settings[2].sensor_size, 3);
+        assert_eq!(app_settings.species_interactions[0][1], 0.5);
+    }
+
+    #[test]
+    fn set_field_rejects_bad_paths() {
+        let mut app_settings = AppSettings::default();
+
+        let out_of_range = app_settings.set_field("species_settings[4].move_speed", "1.0");
+        assert!(out_of_range.unwrap_err().contains("does not exist"));
+
+        let unknown_field = app_settings.set_field("species[0].speed", "1.0");
+        assert!(unknown_field
+            .unwrap_err()
+            .contains("Unknown species setting"));
+
+        let unknown_setting = app_settings.set_field("agent_count", "10");
+        assert!(unknown_setting.unwrap_err().contains("Unknown setting"));
+
+        assert!(app_settings.set_field("width[0]", "10").is_err());
+        assert!(app_settings.set_field("width", "wide").is_err());
+    }
+
+    #[test]
+    fn rejected_values_leave_the_settings_unchanged() {
+        let mut app_settings = AppSettings::default();
+        let num_species = app_settings.num_species();
+
+        assert!(app_settings
+            .set_field("species", "[(), (move_speed: fast)]")
+            .is_err());
+        assert_eq!(app_settings.species_settings.len(), num_species);
+        assert_eq!(app_settings.species_population.len(), num_species);
+    }
+
+    #[test]
+    fn species_lists_follow_any_species_count() {
+        let mut app_settings = AppSettings::default();
+        let species = format!("[{}]", ["()"; 9].join(", "));
+
+        app_settings.set_field("species", &species).unwrap();
+        assert_eq!(app_settings.species_population.len(), 9);
+        assert_eq!(app_settings.species_interactions.len(), 9);
+        assert!(app_settings
+            .species_interactions
+            .iter()
+            .all(|interactions| interactions.len() == 9));
+
+        // every new species gets a colour of its own
+        let colors = &app_settings.species_colors;
+        assert_eq!(colors.len(), 9);
+        for (index, color) in colors.iter().enumerate() {
+            assert!(!colors[..index].contains(color), "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn parse_field_path_splits_indices_and_field() {
+        assert_eq!(parse_field_path("width"), Ok(("width", vec![], None)));
+        assert_eq!(
+            parse_field_path("species_settings[1].move_speed"),
+            Ok(("species_settings", vec![1], Some("move_speed")))
+        );
+        assert_eq!(
+            parse_field_path("species_interactions[0][ 3 ]"),
+            Ok(("species_interactions", vec![0, 3], None))
+        );
+
+        for path in ["species[1", "species[x]", "species[-1]", "species]1["] {
+            assert!(parse_field_path(path).is_err(), "{}", path);
+        }
+    }
+}
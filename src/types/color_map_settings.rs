@@ -0,0 +1,14 @@
+use super::GRADIENT_SIZE;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorMapSettings {
+    /// Linear colours of the palette gradient, the alpha component is unused
+    pub gradient: [[f32; 4]; GRADIENT_SIZE],
+    pub use_gradient: u32,
+    /// Summed trail intensities mapped to the ends of the gradient
+    pub intensity_min: f32,
+    pub intensity_max: f32,
+    /// Exponent of the intensity once mapped to `0..=1`
+    pub gamma: f32,
+}
@@ -1,6 +1,19 @@
+/// Weights the kernel of a diffuse pass can hold, a row of the largest separable kernel or the
+/// largest custom matrix
+pub const MAX_KERNEL_WEIGHTS: usize = 128;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct DiffuseSettings {
     pub decay_rate: f32,
     pub diffuse_rate: f32,
+    pub boundary_mode: u32,
+    pub kernel_radius: u32,
+    /// `kernel_weights` is a whole matrix applied in the first pass, the second one only blends
+    pub kernel_matrix: u32,
+    pub memory_offset_1: u32,
+    pub memory_offset_2: u32,
+    pub memory_offset_3: u32,
+    /// Packed by four, the row applied on both axes or the matrix row after row
+    pub kernel_weights: [[f32; 4]; MAX_KERNEL_WEIGHTS / 4],
 }
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+/// Largest radius of the box and Gaussian kernels, they are applied as a row then a column pass so
+/// the cost only grows linearly with it
+pub const MAX_KERNEL_RADIUS: u32 = 32;
+
+pub const MAX_MATRIX_RADIUS: u32 = 5;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DiffusionKernel {
+    Box {
+        radius: u32,
+    },
+    /// Gaussian weights, cut off at three sigmas
+    Gaussian {
+        sigma: f32,
+    },
+    /// Matrix centred on the pixel with rows going down, weights are normalised to sum to one.
+    /// Missing weights are zero
+    Custom {
+        weights: Vec<Vec<f32>>,
+    },
+}
+
+impl Default for DiffusionKernel {
+    fn default() -> Self {
+        DiffusionKernel::Box { radius: 1 }
+    }
+}
+
+pub struct KernelWeights {
+    pub radius: u32,
+    pub matrix: bool,
+    pub weights: Vec<f32>,
+}
+
+impl DiffusionKernel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiffusionKernel::Box { .. } => "Box",
+            DiffusionKernel::Gaussian { .. } => "Gaussian",
+            DiffusionKernel::Custom { .. } => "Custom",
+        }
+    }
+
+    pub fn weights(&self) -> KernelWeights {
+        match self {
+            DiffusionKernel::Box { radius } => {
+                let radius = (*radius).min(MAX_KERNEL_RADIUS);
+                let size = 2 * radius + 1;
+
+                KernelWeights {
+                    radius,
+                    matrix: false,
+                    weights: vec![1.0 / size as f32; size as usize],
+                }
+            }
+            DiffusionKernel::Gaussian { sigma } => {
+                let sigma = sigma.max(0.1);
+                let radius = ((3.0 * sigma).ceil() as u32).clamp(1, MAX_KERNEL_RADIUS);
+                let weights = (-(radius as i32)..=radius as i32)
+                    .map(|offset| (-(offset * offset) as f32 / (2.0 * sigma * sigma)).exp())
+                    .collect();
+
+                KernelWeights {
+                    radius,
+                    matrix: false,
+                    weights: normalized(weights),
+                }
+            }
+            DiffusionKernel::Custom { weights } => {
+                let half_size = weights
+                    .iter()
+                    .map(Vec::len)
+                    .chain([weights.len()])
+                    .max()
+                    .unwrap_or(0)
+                    / 2;
+                let radius = (half_size as u32).min(MAX_MATRIX_RADIUS) as i32;
+
+                // rows and columns are centred on their own middle, so an odd size lines up exactly
+                let weight = |offset_x: i32, offset_y: i32| {
+                    let row = weights.get((weights.len() as i32 / 2 + offset_y) as usize)?;
+                    row.get((row.len() as i32 / 2 + offset_x) as usize).copied()
+                };
+
+                let matrix = (-radius..=radius)
+                    .flat_map(|offset_y| {
+                        (-radius..=radius)
+                            .map(move |offset_x| weight(offset_x, offset_y).unwrap_or(0.0))
+                    })
+                    .collect();
+
+                KernelWeights {
+                    radius: radius as u32,
+                    matrix: true,
+                    weights: normalized(matrix),
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the total of the trails, weights summing to zero are left as they are
+fn normalized(mut weights: Vec<f32>) -> Vec<f32> {
+    let sum: f32 = weights.iter().sum();
+
+    if sum.abs() > f32::EPSILON {
+        weights.iter_mut().for_each(|weight| *weight /= sum);
+    }
+
+    weights
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// Entries of the lookup table a gradient is baked into for the colour map
+pub const GRADIENT_SIZE: usize = 256;
+
+const VIRIDIS: [(f32, [u8; 3]); 9] = [
+    (0.0, [0x44, 0x01, 0x54]),
+    (0.125, [0x47, 0x2d, 0x7b]),
+    (0.25, [0x3b, 0x52, 0x8b]),
+    (0.375, [0x2c, 0x72, 0x8e]),
+    (0.5, [0x21, 0x91, 0x8c]),
+    (0.625, [0x28, 0xae, 0x80]),
+    (0.75, [0x5e, 0xc9, 0x62]),
+    (0.875, [0xad, 0xdc, 0x30]),
+    (1.0, [0xfd, 0xe7, 0x25]),
+];
+
+const MAGMA: [(f32, [u8; 3]); 9] = [
+    (0.0, [0x00, 0x00, 0x04]),
+    (0.125, [0x1c, 0x10, 0x44]),
+    (0.25, [0x4f, 0x12, 0x7b]),
+    (0.375, [0x81, 0x25, 0x81]),
+    (0.5, [0xb5, 0x36, 0x7a]),
+    (0.625, [0xe5, 0x50, 0x64]),
+    (0.75, [0xfb, 0x87, 0x61]),
+    (0.875, [0xfe, 0xc2, 0x87]),
+    (1.0, [0xfc, 0xfd, 0xbf]),
+];
+
+const FIRE: [(f32, [u8; 3]); 5] = [
+    (0.0, [0x00, 0x00, 0x00]),
+    (0.3, [0xcc, 0x0d, 0x00]),
+    (0.6, [0xff, 0x8c, 0x00]),
+    (0.85, [0xff, 0xf2, 0x4d]),
+    (1.0, [0xff, 0xff, 0xff]),
+];
+
+/// How trail intensities are turned into display colours
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Palette {
+    Species,
+    Viridis,
+    Magma,
+    Fire,
+    /// Gradient of a preset file or the GUI
+    Custom(Gradient),
+}
+
+impl Palette {
+    /// Gradient intensities are mapped through, `None` for the species colours
+    pub fn gradient(&self) -> Option<Gradient> {
+        match self {
+            Palette::Species => None,
+            Palette::Viridis => Some(Gradient::from_srgb8(&VIRIDIS)),
+            Palette::Magma => Some(Gradient::from_srgb8(&MAGMA)),
+            Palette::Fire => Some(Gradient::from_srgb8(&FIRE)),
+            Palette::Custom(gradient) => Some(gradient.clone()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Species => "Species colours",
+            Palette::Viridis => "Viridis",
+            Palette::Magma => "Magma",
+            Palette::Fire => "Fire",
+            Palette::Custom(_) => "Custom",
+        }
+    }
+}
+
+/// Colour stops over the `0..=1` intensity range, interpolated in sRGB
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+    pub stops: Vec<ColorStop>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub position: f32,
+    /// sRGB colour, as picked on screen
+    pub color: [f32; 3],
+}
+
+impl Gradient {
+    fn from_srgb8(stops: &[(f32, [u8; 3])]) -> Self {
+        let stops = stops
+            .iter()
+            .map(|(position, color)| ColorStop {
+                position: *position,
+                color: color.map(|component| component as f32 / 255.0),
+            })
+            .collect();
+
+        Self { stops }
+    }
+
+    /// sRGB colour at `position`, as shown in the GUI
+    pub fn sample(&self, position: f32) -> [f32; 3] {
+        sample_sorted(&self.sorted_stops(), position)
+    }
+
+    /// Adds a stop in the middle of the widest gap, with the colour the gradient already has there
+    pub fn add_stop(&mut self) {
+        let stops = self.sorted_stops();
+        let mut gap = (0.0, 1.0);
+
+        if let (Some(first), Some(last)) = (stops.first(), stops.last()) {
+            let mut gaps = vec![(0.0, first.position), (last.position, 1.0)];
+            gaps.extend(
+                stops
+                    .windows(2)
+                    .map(|pair| (pair[0].position, pair[1].position)),
+            );
+            gap = gaps
+                .into_iter()
+                .max_by(|a, b| (a.1 - a.0).total_cmp(&(b.1 - b.0)))
+                .unwrap_or(gap);
+        }
+
+        let position = (gap.0 + gap.1) / 2.0;
+        self.stops.push(ColorStop {
+            position,
+            color: self.sample(position),
+        });
+        self.stops = self.sorted_stops();
+    }
+
+    /// Linear colours the shader interpolates between, evenly spaced over `0..=1`. Stops may be
+    /// given in any order, the end stops extend outwards
+    pub fn lookup_table(&self) -> [[f32; 4]; GRADIENT_SIZE] {
+        let stops = self.sorted_stops();
+
+        let mut table = [[0.0; 4]; GRADIENT_SIZE];
+        for (index, entry) in table.iter_mut().enumerate() {
+            let color = sample_sorted(&stops, index as f32 / (GRADIENT_SIZE - 1) as f32);
+            let [r, g, b] = color.map(srgb_to_linear);
+            *entry = [r, g, b, 1.0];
+        }
+
+        table
+    }
+
+    fn sorted_stops(&self) -> Vec<ColorStop> {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        stops
+    }
+}
+
+fn sample_sorted(stops: &[ColorStop], position: f32) -> [f32; 3] {
+    let next = stops.iter().position(|stop| stop.position > position);
+
+    match next {
+        None => stops.last().map_or([0.0; 3], |stop| stop.color),
+        Some(0) => stops[0].color,
+        Some(next) => {
+            let (from, to) = (stops[next - 1], stops[next]);
+            let amount = (position - from.position) / (to.position - from.position);
+
+            [0, 1, 2].map(|component| {
+                from.color[component] + (to.color[component] - from.color[component]) * amount
+            })
+        }
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -1,5 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+use super::app_settings::parse_value;
+
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SpeciesSettings {
     pub move_speed: f32,
     pub turn_speed: f32,
@@ -7,11 +12,31 @@ pub struct SpeciesSettings {
     pub sensor_angle_spacing: f32,
     pub sensor_offset_dst: f32,
     pub sensor_size: u32,
+
+    #[serde(skip)]
     pub memory_offset_1: u32,
+    #[serde(skip)]
     pub memory_offset_2: u32,
+    #[serde(skip)]
     pub memory_offset_3: u32,
 }
 
+impl SpeciesSettings {
+    /// Sets a single field from a RON value
+    pub fn set_field(&mut self, field: &str, value: &str) -> Result<(), String> {
+        match field {
+            "move_speed" => self.move_speed = parse_value(value)?,
+            "turn_speed" => self.turn_speed = parse_value(value)?,
+            "sensor_angle_spacing" => self.sensor_angle_spacing = parse_value(value)?,
+            "sensor_offset_dst" => self.sensor_offset_dst = parse_value(value)?,
+            "sensor_size" => self.sensor_size = parse_value(value)?,
+            _ => return Err(format!("Unknown species setting {}", field)),
+        }
+
+        Ok(())
+    }
+}
+
 impl Default for SpeciesSettings {
     fn default() -> Self {
         Self {
@@ -3,4 +3,7 @@
 pub struct TimeBuffer {
     pub time: f32,
     pub delta_time: f32,
+    /// Number of simulation steps since the agents were spawned
+    pub frame: u32,
+    pub seed: u32,
 }